@@ -0,0 +1,134 @@
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+
+use crate::Error;
+
+const MINUTE: Duration = Duration::from_secs(60);
+const HOUR: Duration = Duration::from_secs(60 * 60);
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Modrinth responses stored on disk, keyed by request URL
+///
+/// Entries younger than their time to live are used as is. Older entries are revalidated
+/// with their 'ETag', so unchanged responses don't have to be downloaded again.
+#[derive(Clone, Debug)]
+pub struct Cache {
+    dir: PathBuf,
+    ttl: Option<Duration>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Entry {
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    /// Seconds since the unix epoch when the response was last fetched or revalidated
+    pub fetched_at: u64,
+    pub body: String,
+}
+
+impl Cache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            ttl: None,
+        }
+    }
+
+    /// '<user cache dir>/mod-updater', e.g. '~/.cache/mod-updater' on Linux
+    pub fn default_dir() -> Option<PathBuf> {
+        dirs::cache_dir().map(|dir| dir.join("mod-updater"))
+    }
+
+    /// Use the same time to live for every response instead of one based on the endpoint
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// How long a response from an API path stays fresh, `None` if it isn't cached
+    pub fn ttl(&self, path: &str) -> Option<Duration> {
+        // Tags and published versions rarely change, project version lists change on release
+        let ttl = if path.starts_with("tag/")
+            || path.starts_with("version/")
+            || path.starts_with("version_file/")
+            || path == "versions"
+        {
+            DAY
+        } else if path.starts_with("project/") && path.ends_with("/version") {
+            30 * MINUTE
+        } else if path.starts_with("project/") || path == "projects" {
+            HOUR
+        } else {
+            return None;
+        };
+        Some(self.ttl.unwrap_or(ttl))
+    }
+
+    pub async fn get(&self, url: &str) -> Option<Entry> {
+        let contents = tokio::fs::read(self.path(url)).await.ok()?;
+        serde_json::from_slice::<Entry>(&contents)
+            .ok()
+            .filter(|entry| entry.url == url)
+    }
+
+    pub async fn put(&self, entry: &Entry) -> Result<(), Error> {
+        let path = self.path(&entry.url);
+        tokio::fs::create_dir_all(self.dir.join("http")).await?;
+        // Write to a temporary file first so concurrent readers never see partial entries
+        let tmp = path.with_extension("tmp");
+        tokio::fs::write(&tmp, serde_json::to_vec(entry)?).await?;
+        tokio::fs::rename(&tmp, &path).await?;
+        Ok(())
+    }
+
+    /// Remove every cached response
+    pub async fn clear(&self) -> Result<(), Error> {
+        match tokio::fs::remove_dir_all(self.dir.join("http")).await {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn path(&self, url: &str) -> PathBuf {
+        let key = format!("{:x}", Sha1::digest(url.as_bytes()));
+        self.dir.join("http").join(format!("{key}.json"))
+    }
+}
+
+impl Entry {
+    pub fn new(url: String, etag: Option<String>, body: String) -> Self {
+        Self {
+            url,
+            etag,
+            fetched_at: now(),
+            body,
+        }
+    }
+
+    pub fn is_fresh(&self, ttl: Duration) -> bool {
+        now().saturating_sub(self.fetched_at) < ttl.as_secs()
+    }
+
+    /// Mark the entry as fetched again after the server reported it unchanged
+    pub fn touch(&mut self) {
+        self.fetched_at = now();
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
@@ -2,17 +2,24 @@ use std::{
     collections::BTreeMap,
     fmt::{Debug, Formatter},
     io::ErrorKind,
+    path::{Path, PathBuf},
 };
 
-use modrinth::Loaders;
+use modrinth::{Channel, Loaders, ProjectType, Side};
 use serde::{Deserialize, Serialize};
 use tokio::{
     fs::File,
     io::{AsyncReadExt, AsyncWriteExt},
 };
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
+use output::OutputFormat;
+use store::LinkMode;
 
+pub mod cache;
 pub mod modrinth;
+pub mod mrpack;
+pub mod output;
+pub mod store;
 
 pub enum Error {
     Reqwest(reqwest::Error),
@@ -26,6 +33,22 @@ pub enum Error {
     JoinError(tokio::task::JoinError),
     NoGameVersions,
     InvalidRequest,
+    HashMismatch(String),
+    NotLocked(String),
+    Json(serde_json::Error),
+    Zip(zip::result::ZipError),
+    MissingLoaderVersion,
+    UnsupportedLoader(Loaders),
+    InvalidMrpack(String),
+    Offline(String),
+    MissingDatapacksDir,
+    VersionNotFound(String, String),
+    DryRunUnsupported(String),
+    Ambiguous(String, Vec<String>),
+    UpgradeUnavailable(String, Vec<String>),
+    InvalidHash(String),
+    NotInPack(String),
+    PackExists,
 }
 
 impl From<reqwest::Error> for Error {
@@ -58,6 +81,18 @@ impl From<tokio::task::JoinError> for Error {
     }
 }
 
+impl From<serde_json::Error> for Error {
+    fn from(value: serde_json::Error) -> Self {
+        Self::Json(value)
+    }
+}
+
+impl From<zip::result::ZipError> for Error {
+    fn from(value: zip::result::ZipError) -> Self {
+        Self::Zip(value)
+    }
+}
+
 impl Debug for Error {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -72,6 +107,36 @@ impl Debug for Error {
             Self::JoinError(arg0) => f.debug_tuple("JoinError").field(arg0).finish(),
             Self::NoGameVersions => write!(f, "Failed to get game versions"),
             Self::InvalidRequest => write!(f, "Invalid request"),
+            Self::HashMismatch(arg0) => write!(f, "Hash mismatch for file '{arg0}'"),
+            Self::NotLocked(arg0) => write!(f, "'{arg0}' has no exact version in mods.lock"),
+            Self::Json(arg0) => f.debug_tuple("JSON").field(arg0).finish(),
+            Self::Zip(arg0) => f.debug_tuple("Zip").field(arg0).finish(),
+            Self::MissingLoaderVersion => write!(f, "No loader version set for pack"),
+            Self::UnsupportedLoader(arg0) => write!(f, "Loader '{arg0}' is not supported"),
+            Self::InvalidMrpack(arg0) => write!(f, "Invalid mrpack: {arg0}"),
+            Self::Offline(arg0) => write!(f, "'{arg0}' is not available offline"),
+            Self::MissingDatapacksDir => write!(
+                f,
+                "No 'datapacks_dir' set for pack, e.g. 'saves/<world>/datapacks'"
+            ),
+            Self::VersionNotFound(arg0, arg1) => write!(f, "No version '{arg1}' of '{arg0}' found"),
+            Self::DryRunUnsupported(arg0) => write!(f, "'{arg0}' can't be run with --dry-run"),
+            Self::Ambiguous(arg0, arg1) => write!(
+                f,
+                "Can't choose {arg0} without prompting, candidates are: {}",
+                arg1.join(", ")
+            ),
+            Self::UpgradeUnavailable(arg0, arg1) if arg1.is_empty() => {
+                write!(f, "Can't upgrade to '{arg0}', no compatible game versions found")
+            }
+            Self::UpgradeUnavailable(arg0, arg1) => write!(
+                f,
+                "Can't upgrade to '{arg0}', compatible game versions are: {}",
+                arg1.join(", ")
+            ),
+            Self::InvalidHash(arg0) => write!(f, "'{arg0}' is not a valid sha512 hash"),
+            Self::NotInPack(arg0) => write!(f, "No mod '{arg0}' in pack"),
+            Self::PackExists => write!(f, "'mods.yaml' already exists in current directory"),
         }
     }
 }
@@ -80,11 +145,147 @@ impl Debug for Error {
 pub struct Config {
     pub loader: Loaders,
     pub version: String,
-    pub mods: Vec<String>,
+    /// Version of the mod loader itself, only needed when exporting
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub loader_version: Option<String>,
+    /// Modrinth API base URL, for mirrors or staging instances
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_url: Option<String>,
+    /// Maximum number of Modrinth requests in flight at once
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_concurrency: Option<usize>,
+    /// How files from the shared store are installed into the pack
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link_mode: Option<LinkMode>,
+    /// Folder mods are installed to, relative to 'mods.yaml' (e.g. 'mods' at the instance root)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mods_dir: Option<PathBuf>,
+    /// Folder of the world datapacks are installed to, relative to 'mods.yaml'
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub datapacks_dir: Option<PathBuf>,
+    /// Only use mods for the pack's own loader, instead of falling back to compatible loaders
+    /// (e.g. Fabric mods in a Quilt pack)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub strict_loader: bool,
+    /// Least stable release channel to use versions from, release by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<Channel>,
+    /// Side the pack is installed on, mods only used on the other side are skipped
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub side: Option<Side>,
+    pub mods: Vec<ModEntry>,
+    /// Mods installed automatically because another mod in the pack requires them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dependencies: Vec<String>,
 }
 
 impl Config {
-    const CONFIG_PATH: &str = "mods.yaml";
+    pub const CONFIG_PATH: &str = "mods.yaml";
+
+    pub fn new(loader: Loaders, version: String) -> Self {
+        Self {
+            loader,
+            version,
+            loader_version: None,
+            api_url: None,
+            max_concurrency: None,
+            link_mode: None,
+            mods_dir: None,
+            datapacks_dir: None,
+            strict_loader: false,
+            channel: None,
+            side: None,
+            mods: Vec::new(),
+            dependencies: Vec::new(),
+        }
+    }
+
+    /// Folder mods are installed to, unless 'mods_dir' is set this is 'plugins' for server
+    /// plugin loaders and the pack folder itself otherwise
+    pub fn mods_dir(&self) -> &Path {
+        match &self.mods_dir {
+            Some(mods_dir) => mods_dir,
+            None if self.loader.is_plugin_loader() => Path::new("plugins"),
+            None => Path::new("."),
+        }
+    }
+
+    /// Folder content of a project type is installed to
+    pub fn content_dir(&self, project_type: ProjectType) -> Result<&Path, Error> {
+        match project_type {
+            ProjectType::Mod => Ok(self.mods_dir()),
+            ProjectType::Resourcepack => Ok(Path::new("resourcepacks")),
+            ProjectType::Shader => Ok(Path::new("shaderpacks")),
+            ProjectType::Datapack => self
+                .datapacks_dir
+                .as_deref()
+                .ok_or(Error::MissingDatapacksDir),
+        }
+    }
+
+    /// Modrinth loaders to look for versions of a project type with, in order of preference
+    pub fn loader_tiers(&self, project_type: ProjectType, game_version: &str) -> Vec<Vec<String>> {
+        if project_type == ProjectType::Mod && !self.strict_loader {
+            self.loader.tiers(game_version)
+        } else {
+            vec![project_type.loaders(&self.loader)]
+        }
+    }
+
+    /// Side a mod is used on, its override in 'mods.yaml' or else `side` as reported by Modrinth
+    pub fn side(&self, name: &str, side: Option<Side>) -> Side {
+        self.entry(name)
+            .and_then(|entry| entry.side)
+            .or(side)
+            .unwrap_or_default()
+    }
+
+    /// Whether a mod is used on the side the pack is installed on, given `side` as reported by
+    /// Modrinth
+    pub fn uses(&self, name: &str, side: Option<Side>) -> bool {
+        self.side
+            .is_none_or(|pack_side| self.side(name, side).includes(pack_side))
+    }
+
+    /// Release channel of a mod, its own if set or else the pack's
+    pub fn channel(&self, name: &str) -> Channel {
+        self.entry(name)
+            .and_then(|entry| entry.channel)
+            .or(self.channel)
+            .unwrap_or_default()
+    }
+
+    pub fn entry(&self, name: &str) -> Option<&ModEntry> {
+        self.mods.iter().find(|entry| entry.name == name)
+    }
+
+    pub fn entry_mut(&mut self, name: &str) -> Option<&mut ModEntry> {
+        self.mods.iter_mut().find(|entry| entry.name == name)
+    }
+
+    /// Version a mod is pinned to, if any
+    pub fn pin(&self, name: &str) -> Option<&str> {
+        self.entry(name).and_then(|entry| entry.pin.as_deref())
+    }
+
+    pub fn contains_mod(&self, name: &str) -> bool {
+        self.entry(name).is_some()
+    }
+
+    /// Project type of a mod or dependency in the pack, dependencies are always mods
+    pub fn project_type(&self, name: &str) -> ProjectType {
+        self.entry(name)
+            .map(|entry| entry.project_type)
+            .unwrap_or_default()
+    }
+
+    /// Names of all mods and dependencies in the pack
+    pub fn all_mods(&self) -> impl Iterator<Item = &String> {
+        self.mods
+            .iter()
+            .map(|entry| &entry.name)
+            .chain(self.dependencies.iter())
+    }
 
     pub async fn try_load() -> Result<Config, Error> {
         match tokio::fs::File::open(Self::CONFIG_PATH).await {
@@ -103,7 +304,8 @@ impl Config {
     }
 
     pub async fn try_save(&mut self) -> Result<(), Error> {
-        self.mods.sort();
+        self.mods.sort_by(|a, b| a.name.cmp(&b.name));
+        self.dependencies.sort();
         let contents = serde_yaml::to_string(&self)?;
         let mut file = File::create(Self::CONFIG_PATH).await?;
         file.write_all(contents.as_bytes()).await?;
@@ -111,10 +313,144 @@ impl Config {
     }
 }
 
+/// Mod in 'mods.yaml', written as just its name unless it has other settings
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(from = "RawModEntry", into = "RawModEntry")]
+pub struct ModEntry {
+    pub name: String,
+    pub project_type: ProjectType,
+    /// Overrides the side Modrinth reports the project is used on
+    pub side: Option<Side>,
+    /// Overrides the pack's release channel
+    pub channel: Option<Channel>,
+    /// Version id, number or name the mod is kept at instead of being updated
+    pub pin: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum RawModEntry {
+    Name(String),
+    Entry {
+        name: String,
+        #[serde(default, rename = "type")]
+        project_type: ProjectType,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        side: Option<Side>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        channel: Option<Channel>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pin: Option<String>,
+    },
+}
+
+impl ModEntry {
+    pub fn new(name: String, project_type: ProjectType) -> Self {
+        Self {
+            name,
+            project_type,
+            side: None,
+            channel: None,
+            pin: None,
+        }
+    }
+}
+
+impl From<RawModEntry> for ModEntry {
+    fn from(value: RawModEntry) -> Self {
+        match value {
+            RawModEntry::Name(name) => Self::new(name, ProjectType::Mod),
+            RawModEntry::Entry {
+                name,
+                project_type,
+                side,
+                channel,
+                pin,
+            } => Self {
+                name,
+                project_type,
+                side,
+                channel,
+                pin,
+            },
+        }
+    }
+}
+
+impl From<ModEntry> for RawModEntry {
+    fn from(value: ModEntry) -> Self {
+        if value == ModEntry::new(value.name.clone(), ProjectType::Mod) {
+            Self::Name(value.name)
+        } else {
+            Self::Entry {
+                name: value.name,
+                project_type: value.project_type,
+                side: value.side,
+                channel: value.channel,
+                pin: value.pin,
+            }
+        }
+    }
+}
+
+impl PartialEq<&str> for ModEntry {
+    fn eq(&self, other: &&str) -> bool {
+        self.name == *other
+    }
+}
+
+/// Exact version and file of a mod installed in the pack, as recorded in 'mods.lock'
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct InstalledMod {
     pub version: String,
     pub file: String,
+    #[serde(default)]
+    pub project_id: String,
+    #[serde(default)]
+    pub version_id: String,
+    #[serde(default)]
+    pub url: String,
+    #[serde(default)]
+    pub size: i32,
+    #[serde(default)]
+    pub sha512: String,
+    #[serde(default)]
+    pub sha1: String,
+    /// Loader the version was chosen for, when it isn't the pack's own loader
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub loader: String,
+    /// Side the project is used on according to Modrinth
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub side: Option<Side>,
+}
+
+impl InstalledMod {
+    pub fn new(version: &modrinth::Version, file: &modrinth::File) -> Self {
+        Self {
+            version: version.name.clone(),
+            file: file.filename.clone(),
+            project_id: version.project_id.clone(),
+            version_id: version.id.clone(),
+            url: file.url.clone(),
+            size: file.size,
+            sha512: file.hashes.sha512.clone(),
+            sha1: file.hashes.sha1.clone(),
+            loader: String::new(),
+            side: None,
+        }
+    }
+
+    /// Entries written by older versions only record the version name and file
+    pub fn is_locked(&self) -> bool {
+        !self.version_id.is_empty() && !self.url.is_empty() && !self.sha512.is_empty()
+    }
+
+    pub fn hashes(&self) -> modrinth::Hash {
+        modrinth::Hash {
+            sha512: self.sha512.clone(),
+            sha1: self.sha1.clone(),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -123,31 +459,42 @@ pub struct ModManifest {
 }
 
 impl ModManifest {
-    const CONFIG_PATH: &str = ".installed.yaml";
+    const CONFIG_PATH: &str = "mods.lock";
+    const LEGACY_CONFIG_PATH: &str = ".installed.yaml";
 
     pub async fn try_load() -> Result<ModManifest, Error> {
-        match tokio::fs::File::open(Self::CONFIG_PATH).await {
-            Ok(mut file) => {
-                let mut contents = String::new();
-                file.read_to_string(&mut contents).await?;
-                Ok(serde_yaml::from_str(&contents)?)
-            }
-            Err(err) => {
-                if err.kind() == ErrorKind::NotFound {
-                    Ok(ModManifest {
-                        installed: BTreeMap::new(),
-                    })
-                } else {
-                    Err(err.into())
+        Self::try_load_from(Path::new("")).await
+    }
+
+    /// Load the manifest of the pack in `dir`
+    pub async fn try_load_from(dir: &Path) -> Result<ModManifest, Error> {
+        for path in [Self::CONFIG_PATH, Self::LEGACY_CONFIG_PATH] {
+            match tokio::fs::File::open(dir.join(path)).await {
+                Ok(mut file) => {
+                    let mut contents = String::new();
+                    file.read_to_string(&mut contents).await?;
+                    return Ok(serde_yaml::from_str(&contents)?);
+                }
+                Err(err) => {
+                    if err.kind() != ErrorKind::NotFound {
+                        return Err(err.into());
+                    }
                 }
             }
         }
+
+        Ok(ModManifest {
+            installed: BTreeMap::new(),
+        })
     }
 
     pub async fn try_save(&self) -> Result<(), Error> {
         let contents = serde_yaml::to_string(&self)?;
         let mut file = File::create(Self::CONFIG_PATH).await?;
         file.write_all(contents.as_bytes()).await?;
+        if tokio::fs::try_exists(Self::LEGACY_CONFIG_PATH).await? {
+            tokio::fs::remove_file(Self::LEGACY_CONFIG_PATH).await?;
+        }
         Ok(())
     }
 }
@@ -158,6 +505,39 @@ impl ModManifest {
 pub struct Cli {
     #[command(subcommand)]
     pub command: Commands,
+    /// Folder containing 'mods.yaml' [default: current folder]
+    #[arg(long, global = true, env = "MOD_UPDATER_PACK_DIR")]
+    pub pack_dir: Option<PathBuf>,
+    /// Modrinth API base URL, overrides 'api_url' in 'mods.yaml'
+    #[arg(long, global = true, env = "MODRINTH_API_URL")]
+    pub api_url: Option<String>,
+    /// Maximum number of Modrinth requests in flight at once [default: 8]
+    #[arg(long, global = true, env = "MOD_UPDATER_MAX_CONCURRENCY")]
+    pub max_concurrency: Option<usize>,
+    /// Always fetch from Modrinth instead of using cached responses
+    #[arg(long, global = true)]
+    pub no_cache: bool,
+    /// Folder for cached Modrinth responses [default: <user cache dir>/mod-updater]
+    #[arg(long, global = true, env = "MOD_UPDATER_CACHE_DIR")]
+    pub cache_dir: Option<PathBuf>,
+    /// Only use cached responses and stored files instead of connecting to Modrinth
+    #[arg(long, global = true)]
+    pub offline: bool,
+    /// Folder for downloaded files shared by all packs [default: <user data dir>/mod-updater/store]
+    #[arg(long, global = true, env = "MOD_UPDATER_STORE_DIR")]
+    pub store_dir: Option<PathBuf>,
+    /// How stored files are installed, overrides 'link_mode' in 'mods.yaml' [default: hardlink]
+    #[arg(long, global = true, value_enum, env = "MOD_UPDATER_LINK_MODE")]
+    pub link_mode: Option<LinkMode>,
+    /// Print what would change without changing any files
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+    /// Print results as text, JSON or YAML, progress messages then go to stderr
+    #[arg(long, global = true, value_enum, default_value_t)]
+    pub output: OutputFormat,
+    /// Never prompt, choose deterministically or fail when a choice is ambiguous
+    #[arg(short, long, global = true, visible_alias = "non-interactive")]
+    pub yes: bool,
 }
 
 #[derive(Subcommand, Clone)]
@@ -181,6 +561,9 @@ pub enum Commands {
         loader: Loaders,
         /// Filter by game version (e.g. 1.21.4)
         game_version: Option<String>,
+        /// Least stable release channel to consider
+        #[arg(long, value_enum, default_value_t)]
+        channel: Channel,
     },
     /// Download mod given a loader and game version
     Download {
@@ -193,33 +576,118 @@ pub enum Commands {
         /// Download latest mod version (skip mod version selection)
         #[arg(short, long)]
         latest: bool,
+        /// Least stable release channel to consider
+        #[arg(long, value_enum, default_value_t)]
+        channel: Channel,
     },
     /// Operate on a mod pack specified in 'mods.yaml'
     Pack {
         #[command(subcommand)]
         command: PackCommand,
     },
+    /// Manage cached Modrinth responses
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+    /// Manage files downloaded to the store shared by all packs
+    Store {
+        #[command(subcommand)]
+        command: StoreCommand,
+    },
+}
+
+#[derive(Subcommand, Clone)]
+pub enum CacheCommand {
+    /// Remove all cached responses
+    Clear,
+}
+
+#[derive(Subcommand, Clone)]
+pub enum StoreCommand {
+    /// Remove stored files that no known pack uses anymore
+    Gc,
 }
 
 #[derive(Subcommand, Clone)]
 pub enum PackCommand {
     /// Download the latest version of all mods in pack
-    Download,
+    Download {
+        /// Only download mods used on this side, remembered for later commands
+        #[arg(long, value_enum)]
+        side: Option<Side>,
+    },
+    /// Install the mod versions recorded in 'mods.lock'
+    Install {
+        /// Fail instead of resolving mods that aren't locked
+        #[arg(long)]
+        frozen: bool,
+    },
     /// Update mods to their latest versions
     Update,
     /// Check for compatible game versions and update all mods to selected version
-    Upgrade,
+    Upgrade {
+        /// Game version to upgrade to instead of prompting for one
+        #[arg(long)]
+        to: Option<String>,
+    },
     /// Create modpack definition
     Init {
         loader: Loaders,
         game_version: String,
+        /// Folder to install mods to, relative to the pack folder
+        #[arg(long)]
+        mods_dir: Option<PathBuf>,
     },
     /// Add mod to modpack
-    Add { mod_name: String },
+    Add {
+        mod_name: String,
+        /// Kind of project, installed to 'mods', 'resourcepacks', 'shaderpacks' or 'datapacks_dir'
+        #[arg(short = 't', long = "type", value_enum, default_value_t = ProjectType::Mod)]
+        project_type: ProjectType,
+    },
     /// Remove mod from modpack
     Remove { mod_name: String },
+    /// Keep a mod at its installed version, or the given one, instead of updating it
+    Pin {
+        mod_name: String,
+        /// Version id, number or name to pin (defaults to the installed version)
+        version: Option<String>,
+    },
+    /// Let a pinned mod be updated again
+    Unpin { mod_name: String },
     /// List mods in modpack
     List,
     /// List the latest game version for all mods in pack
     LatestGameVersion,
+    /// List mods with newer versions for the pack's loader and game version, fails if there are any
+    Outdated,
+    /// Export modpack so it can be imported by other launchers
+    Export {
+        /// Archive format
+        #[arg(short, long, value_enum, default_value_t = ExportFormat::Mrpack)]
+        format: ExportFormat,
+        /// File to write (defaults to '<NAME>.mrpack')
+        #[arg(short = 'o', long)]
+        file: Option<PathBuf>,
+        /// Pack name (defaults to the current folder name)
+        #[arg(long)]
+        name: Option<String>,
+        /// Pack version
+        #[arg(long, default_value = "1.0.0")]
+        pack_version: String,
+        /// Mod loader version (e.g. 0.16.10), overrides 'loader_version' in 'mods.yaml'
+        #[arg(long)]
+        loader_version: Option<String>,
+    },
+    /// Create modpack definition from a Modrinth modpack (.mrpack)
+    Import { file: PathBuf },
+    /// Add jars in the pack folder that aren't tracked yet, identified by their hash
+    Adopt,
+}
+
+#[derive(ValueEnum, Clone)]
+pub enum ExportFormat {
+    /// Modrinth modpack (.mrpack)
+    Mrpack,
 }
@@ -1,12 +1,27 @@
 use clap::Parser;
-use mod_updater::modrinth::{GameVersion, Loaders, SearchResult, Version, VersionType};
-use mod_updater::{Config, Error, InstalledMod, ModManifest, Cli, Commands, PackCommand};
-use reqwest::{Client, ClientBuilder};
-use std::collections::{HashMap, HashSet};
+use mod_updater::cache::Cache;
+use mod_updater::modrinth::{
+    Channel, Dependency, File, GameVersion, Hash, HashAlgorithm, Loaders, ModrinthClient,
+    ProjectType, Side, Version, VersionType,
+};
+use mod_updater::mrpack::{self, Hashes, Index, IndexFile};
+use mod_updater::output::{
+    ClearedCache, Export, LatestGameVersion, ListedMod, ModUpdate, OutdatedMod, OutputFormat,
+    PackChanges, PackList, Pin, StoreGc, UpdateStatus, Upgrade,
+};
+use mod_updater::store::{LinkMode, Store};
+use mod_updater::{
+    CacheCommand, Cli, Commands, Config, Error, ExportFormat, InstalledMod, ModEntry, ModManifest,
+    PackCommand, StoreCommand,
+};
+use sha1::Sha1;
+use sha2::{Digest, Sha512};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::io::stdin;
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::fs::{copy, create_dir, read_dir, remove_file, try_exists};
+use tokio::fs::{copy, create_dir, create_dir_all, read_dir, remove_file, rename, try_exists};
 use tokio::io::{stdout, AsyncWriteExt};
 use tokio::task::{spawn_blocking, JoinSet};
 
@@ -18,10 +33,125 @@ static APP_USER_AGENT: &str = concat!(
     env!("CARGO_PKG_VERSION"),
 );
 
+/// What commands run with besides their arguments
+#[derive(Clone)]
+struct Context {
+    client: ModrinthClient,
+    /// Store for downloaded files, used to avoid downloading a file twice
+    store: Option<Store>,
+    /// Only report the changes that would be made, requests are still sent
+    dry_run: bool,
+    /// Never prompt for a choice
+    non_interactive: bool,
+    /// Bytes a dry run would have downloaded
+    planned_download_bytes: Arc<AtomicU64>,
+    /// Format results are printed in, stdout is reserved for them unless it's text
+    output: OutputFormat,
+}
+
+/// Print progress and prompts to stdout, or to stderr when printing a JSON or YAML result
+macro_rules! status {
+    ($ctx:expr, $($arg:tt)*) => {
+        if $ctx.output.is_text() {
+            println!($($arg)*);
+        } else {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Error> {
-    let cli = Cli::parse();
-    let client = ClientBuilder::new().user_agent(APP_USER_AGENT).build()?;
+    let mut cli = Cli::parse();
+    let dry_run = cli.dry_run;
+    if dry_run {
+        let unsupported = match &cli.command {
+            Commands::Cache { .. } => Some("cache"),
+            Commands::Store { .. } => Some("store"),
+            Commands::Pack { command } => match command {
+                PackCommand::Init { .. } => Some("pack init"),
+                PackCommand::Import { .. } => Some("pack import"),
+                PackCommand::Export { .. } => Some("pack export"),
+                PackCommand::Adopt => Some("pack adopt"),
+                _ => None,
+            },
+            _ => None,
+        };
+        if let Some(command) = unsupported {
+            return Err(Error::DryRunUnsupported(command.to_string()));
+        }
+    }
+    if let Some(pack_dir) = cli.pack_dir.take() {
+        // Paths given on the command line are relative to where it was run
+        for path in [&mut cli.cache_dir, &mut cli.store_dir]
+            .into_iter()
+            .flatten()
+        {
+            *path = std::path::absolute(&path)?;
+        }
+        match &mut cli.command {
+            Commands::Pack {
+                command: PackCommand::Import { file },
+            } => *file = std::path::absolute(&file)?,
+            Commands::Pack {
+                command:
+                    PackCommand::Export {
+                        file: Some(file), ..
+                    },
+            } => *file = std::path::absolute(&file)?,
+            Commands::Pack {
+                command: PackCommand::Init { .. },
+            } => create_dir_all(&pack_dir).await?,
+            _ => {}
+        }
+        std::env::set_current_dir(pack_dir)?;
+    }
+    let mut client =
+        ModrinthClient::new(APP_USER_AGENT)?.with_notice_handler(|notice| eprintln!("{notice}"));
+
+    // Settings in 'mods.yaml' apply when no option or environment variable is given
+    let pack_config =
+        if matches!(cli.command, Commands::Pack { .. }) && try_exists(Config::CONFIG_PATH).await? {
+            Some(Config::try_load().await?)
+        } else {
+            None
+        };
+    let api_url = cli.api_url.or_else(|| {
+        pack_config
+            .as_ref()
+            .and_then(|config| config.api_url.clone())
+    });
+    if let Some(api_url) = api_url {
+        client = client.with_base_url(&api_url);
+    }
+    let max_concurrency = cli.max_concurrency.or_else(|| {
+        pack_config
+            .as_ref()
+            .and_then(|config| config.max_concurrency)
+    });
+    if let Some(max_concurrency) = max_concurrency {
+        client = client.with_max_concurrency(max_concurrency);
+    }
+    let cache = cli.cache_dir.or_else(Cache::default_dir).map(Cache::new);
+    if let Some(cache) = cache.clone().filter(|_| !cli.no_cache) {
+        client = client.with_cache(cache);
+    }
+    let link_mode = cli
+        .link_mode
+        .or_else(|| pack_config.as_ref().and_then(|config| config.link_mode))
+        .unwrap_or_default();
+    let store = cli
+        .store_dir
+        .or_else(Store::default_dir)
+        .map(|dir| Store::new(dir).with_link_mode(link_mode));
+    let ctx = Context {
+        client: client.with_offline(cli.offline),
+        store: store.clone(),
+        dry_run,
+        non_interactive: cli.yes,
+        planned_download_bytes: Arc::new(AtomicU64::new(0)),
+        output: cli.output,
+    };
 
     match cli.command {
         Commands::Versions {
@@ -29,74 +159,182 @@ async fn main() -> Result<(), Error> {
             loader,
             game_version,
         } => {
-            list_versions(client.clone(), mod_name, loader, game_version).await?;
+            list_versions(ctx.clone(), mod_name, loader, game_version).await?;
         }
         Commands::Latest {
             mod_name,
             loader,
             game_version,
+            channel,
         } => {
-            get_latest_version(client.clone(), mod_name, loader, game_version).await?;
+            get_latest_version(ctx.clone(), mod_name, loader, game_version, channel).await?;
         }
         Commands::Download {
             mod_name,
             loader,
             game_version,
             latest,
+            channel,
         } => {
-            download_mod(client.clone(), mod_name, loader, game_version, latest).await?;
+            let tiers = loader.tiers(&game_version);
+            // No pack keeps the file in use, so 'store gc' could remove what a link points to
+            let ctx = Context {
+                store: ctx
+                    .store
+                    .clone()
+                    .map(|store| store.with_link_mode(LinkMode::Copy)),
+                ..ctx.clone()
+            };
+            let (_name, installed_mod, _dependencies) = download_mod(
+                ctx.clone(),
+                mod_name,
+                tiers,
+                game_version,
+                channel,
+                latest,
+                PathBuf::from("."),
+            )
+            .await?;
+            ctx.output.print(&installed_mod)?;
         }
+        Commands::Cache { command } => match command {
+            CacheCommand::Clear => clear_cache(&ctx, cache).await?,
+        },
+        Commands::Store { command } => match command {
+            StoreCommand::Gc => store_gc(&ctx).await?,
+        },
         Commands::Pack { command } => {
             let manifest = ModManifest::try_load().await?;
             match command {
-                PackCommand::Download => {
-                    download_mods(client.clone(), Config::try_load().await?, manifest).await?;
+                PackCommand::Download { side } => {
+                    let mut config = Config::try_load().await?;
+                    config.side = side.or(config.side);
+                    ctx.output
+                        .print(&download_mods(ctx.clone(), config, manifest).await?)?;
+                }
+                PackCommand::Install { frozen } => {
+                    let changes =
+                        install_mods(ctx.clone(), Config::try_load().await?, manifest, frozen)
+                            .await?;
+                    ctx.output.print(&changes)?;
                 }
                 PackCommand::Update => {
-                    update_mods(client.clone(), Config::try_load().await?).await?;
+                    update_mods(ctx.clone(), Config::try_load().await?, manifest).await?;
                 }
-                PackCommand::Upgrade => {
-                    upgrade_mods(client.clone(), Config::try_load().await?, manifest).await?;
+                PackCommand::Upgrade { to } => {
+                    upgrade_mods(ctx.clone(), Config::try_load().await?, manifest, to).await?;
                 }
                 PackCommand::Init {
                     loader,
                     game_version,
+                    mods_dir,
                 } => {
-                    pack_init(loader, game_version).await?;
+                    pack_init(&ctx, loader, game_version, mods_dir).await?;
                 }
-                PackCommand::Add { mod_name } => {
+                PackCommand::Add {
+                    mod_name,
+                    project_type,
+                } => {
                     add_mod(
-                        client.clone(),
+                        ctx.clone(),
                         Config::try_load().await?,
                         manifest,
                         mod_name,
+                        project_type,
                     )
                     .await?;
                 }
                 PackCommand::Remove { mod_name } => {
-                    remove_mod(Config::try_load().await?, manifest, mod_name).await?
+                    remove_mod(ctx.clone(), Config::try_load().await?, manifest, mod_name).await?
+                }
+                PackCommand::Pin { mod_name, version } => {
+                    pin_mod(
+                        ctx.clone(),
+                        Config::try_load().await?,
+                        manifest,
+                        mod_name,
+                        version,
+                    )
+                    .await?
+                }
+                PackCommand::Unpin { mod_name } => {
+                    unpin_mod(ctx.clone(), Config::try_load().await?, mod_name).await?
                 }
-                PackCommand::List => list_mods(Config::try_load().await?).await?,
+                PackCommand::List => list_mods(&ctx, Config::try_load().await?, manifest).await?,
                 PackCommand::LatestGameVersion => {
-                    latest_game_version(client.clone(), Config::try_load().await?).await?
+                    latest_game_version(ctx.clone(), Config::try_load().await?).await?
+                }
+                PackCommand::Outdated => {
+                    let outdated =
+                        outdated_mods(ctx.clone(), Config::try_load().await?, manifest).await?;
+                    // Errors exit with 1, so CI can tell an outdated pack from a failed check
+                    if outdated {
+                        std::process::exit(2);
+                    }
+                }
+                PackCommand::Import { file } => import_pack(ctx.clone(), file).await?,
+                PackCommand::Adopt => {
+                    adopt_mods(ctx.clone(), Config::try_load().await?, manifest).await?
+                }
+                PackCommand::Export {
+                    format: export_format,
+                    file,
+                    name,
+                    pack_version,
+                    loader_version,
+                } => {
+                    let export = export_pack(
+                        Config::try_load().await?,
+                        manifest,
+                        export_format,
+                        file,
+                        name,
+                        pack_version,
+                        loader_version,
+                    )
+                    .await?;
+                    status!(ctx, "Exported pack to '{}'", export.file.display());
+                    ctx.output.print(&export)?
+                }
+            }
+
+            // Keep track of packs using the store so 'store gc' knows which files are in use
+            if let Some(store) = store.filter(|_| !dry_run) {
+                if try_exists(Config::CONFIG_PATH).await? {
+                    store.add_pack(&std::env::current_dir()?).await?;
                 }
             }
         }
     }
 
+    if dry_run {
+        status!(
+            ctx,
+            "Dry run, nothing was changed ({} bytes to download)",
+            ctx.planned_download_bytes.load(Ordering::Relaxed)
+        );
+    }
+
     Ok(())
 }
 
 async fn list_versions(
-    client: Client,
+    ctx: Context,
     mod_name: String,
     loader: Option<Loaders>,
     game_version: Option<String>,
 ) -> Result<(), Error> {
-    let versions = get_versions(client, mod_name.clone(), loader, game_version).await?;
-    println!("Mod versions for '{mod_name}':");
+    let versions = ctx
+        .client
+        .project_versions(&mod_name, loader.as_ref(), game_version.as_deref())
+        .await?;
+    if !ctx.output.is_text() {
+        return ctx.output.print(&versions);
+    }
+    status!(ctx, "Mod versions for '{mod_name}':");
     for version in versions {
-        println!(
+        status!(
+            ctx,
             "\t{} - {} {}",
             version.name,
             version.game_versions.join(", "),
@@ -108,49 +346,57 @@ async fn list_versions(
 }
 
 async fn get_latest_version(
-    client: Client,
+    ctx: Context,
     mod_name: String,
     loader: Loaders,
     game_version: Option<String>,
+    channel: Channel,
 ) -> Result<(), Error> {
-    let versions = get_versions(client, mod_name.clone(), Some(loader), game_version).await?;
-    let latest = versions.first();
-    println!("Latest version for mod '{}':", mod_name.clone());
+    let versions = ctx
+        .client
+        .project_versions(&mod_name, Some(&loader), game_version.as_deref())
+        .await?;
+    let latest = versions.iter().find(|version| channel.allows(version));
+    if !ctx.output.is_text() {
+        return ctx.output.print(&latest);
+    }
+    status!(ctx, "Latest version for mod '{}':", mod_name.clone());
     if let Some(latest) = latest {
-        println!("\t{} - {}", latest.name, latest.game_versions.join(", "));
+        status!(
+            ctx,
+            "\t{} - {}",
+            latest.name,
+            latest.game_versions.join(", ")
+        );
     } else {
-        println!("No versions found for mod '{mod_name}'");
+        status!(ctx, "No versions found for mod '{mod_name}'");
     }
 
     Ok(())
 }
 
 async fn download_mod(
-    client: Client,
+    ctx: Context,
     mod_name: String,
-    loader: Loaders,
+    tiers: Vec<Vec<String>>,
     game_version: String,
+    channel: Channel,
     latest: bool,
-) -> Result<(String, InstalledMod), Error> {
-    let versions = get_versions(
-        client.clone(),
-        mod_name.clone(),
-        Some(loader),
-        Some(game_version),
-    )
-    .await?;
+    dir: PathBuf,
+) -> Result<(String, InstalledMod, Vec<Dependency>), Error> {
+    let versions = tiered_versions(&ctx, &mod_name, &tiers, &game_version, channel).await?;
     if versions.is_empty() {
         return Err(Error::NoVersionsFound);
     }
 
-    let version = if versions.len() == 1 || latest {
+    let version = if versions.len() == 1 || latest || ctx.non_interactive {
         &versions[0]
     } else {
-        println!("Available versions:");
+        status!(ctx, "Available versions:");
         for (i, version) in versions.iter().enumerate() {
-            println!("\t{i} - {}", version.name);
+            status!(ctx, "\t{i} - {}", version.name);
         }
-        println!("Select version (0-{}):", versions.len() - 1);
+        status!(ctx, "Select version (0-{}):", versions.len() - 1);
         let buffer: String = spawn_blocking(move || {
             let mut buffer = String::new();
 
@@ -181,13 +427,20 @@ async fn download_mod(
 
     let file = if files.len() == 1 {
         &files[0]
+    } else if ctx.non_interactive {
+        files.iter().find(|file| file.primary).ok_or_else(|| {
+            Error::Ambiguous(
+                format!("a file of '{}'", version.name),
+                files.iter().map(|file| file.filename.clone()).collect(),
+            )
+        })?
     } else {
-        println!("Available files:");
+        status!(ctx, "Available files:");
         for (i, file) in version.files.iter().enumerate() {
-            println!("\t{i} - {}", file.filename);
+            status!(ctx, "\t{i} - {}", file.filename);
         }
 
-        println!("Select file (0-{}):", version.files.len() - 1);
+        status!(ctx, "Select file (0-{}):", version.files.len() - 1);
         let buffer = spawn_blocking(move || {
             let mut buffer = String::new();
             match stdin().read_line(&mut buffer) {
@@ -208,294 +461,1038 @@ async fn download_mod(
         &files[file_i]
     };
 
-    download_file(client.clone(), file.url.clone(), file.filename.clone()).await?;
+    download_file(
+        ctx.clone(),
+        file.url.clone(),
+        mod_path(&dir, &file.filename),
+        file.hashes.clone(),
+        file.size as u64,
+    )
+    .await?;
 
-    Ok((
-        mod_name,
-        InstalledMod {
-            version: version.name.clone(),
-            file: file.filename.clone(),
-        },
-    ))
+    let mut installed_mod = InstalledMod::new(version, file);
+    installed_mod.loader = chosen_loader(version, &tiers);
+    Ok((mod_name, installed_mod, version.dependencies.clone()))
 }
 
-async fn download_file(client: Client, url: String, path: String) -> Result<(), Error> {
-    let request = client.get(url);
+/// Versions of a project on `channel` for the first tier of loaders that has any, newest first
+async fn tiered_versions(
+    ctx: &Context,
+    mod_name: &str,
+    tiers: &[Vec<String>],
+    game_version: &str,
+    channel: Channel,
+) -> Result<Vec<Version>, Error> {
+    for (i, loaders) in tiers.iter().enumerate() {
+        let mut versions = ctx
+            .client
+            .project_versions_for(mod_name, loaders, Some(game_version))
+            .await?;
+        versions.retain(|version| channel.allows(version));
+        if !versions.is_empty() {
+            if i > 0 {
+                status!(
+                    ctx,
+                    "No {} version of '{mod_name}' found, falling back to {}",
+                    tiers[0].join("/"),
+                    loaders.join("/")
+                );
+            }
+            return Ok(versions);
+        }
+    }
+    Ok(Vec::new())
+}
 
-    println!("Downloading '{}'...", path);
-    stdout().flush().await?;
-    let res = request.send().await?;
+/// Loader to record for a version chosen from `tiers`, empty when it is for the pack's own loader
+fn chosen_loader(version: &Version, tiers: &[Vec<String>]) -> String {
+    match tiers
+        .iter()
+        .position(|loaders| version.loaders.iter().any(|l| loaders.contains(l)))
+    {
+        Some(i) if i > 0 => tiers[i]
+            .iter()
+            .find(|l| version.loaders.contains(l))
+            .cloned()
+            .unwrap_or_default(),
+        _ => String::new(),
+    }
+}
+
+async fn download_version(
+    ctx: Context,
+    version: &Version,
+    dir: &Path,
+    tiers: &[Vec<String>],
+) -> Result<InstalledMod, Error> {
+    let file = primary_file(version)?;
+
+    download_file(
+        ctx.clone(),
+        file.url.clone(),
+        mod_path(dir, &file.filename),
+        file.hashes.clone(),
+        file.size as u64,
+    )
+    .await?;
+
+    let mut installed_mod = InstalledMod::new(version, file);
+    installed_mod.loader = chosen_loader(version, tiers);
+    Ok(installed_mod)
+}
+
+/// File of a version to install, its primary file or else the first one
+fn primary_file(version: &Version) -> Result<&File, Error> {
+    version
+        .files
+        .iter()
+        .find(|file| file.primary)
+        .or(version.files.first())
+        .ok_or(Error::NoFilesFound)
+}
+
+/// Install every required dependency (and their dependencies) that isn't already part of the pack
+async fn install_dependencies(
+    ctx: Context,
+    config: &mut Config,
+    manifest: &mut ModManifest,
+    dependencies: Vec<Dependency>,
+) -> Result<Vec<String>, Error> {
+    let mut installed = Vec::new();
+    let mut queue = dependencies;
+
+    while let Some(dependency) = queue.pop() {
+        if dependency.dependency_type != "required" {
+            continue;
+        }
+
+        // Avoid looking up projects that are already installed
+        if let Some(project_id) = &dependency.project_id {
+            if manifest
+                .installed
+                .values()
+                .any(|installed_mod| installed_mod.project_id == *project_id)
+            {
+                continue;
+            }
+        }
+
+        let version = if let Some(version_id) = &dependency.version_id {
+            Some(ctx.client.version(version_id).await?)
+        } else {
+            None
+        };
+        let project_id = match (&version, &dependency.project_id) {
+            (Some(version), _) => version.project_id.clone(),
+            (None, Some(project_id)) => project_id.clone(),
+            (None, None) => continue,
+        };
+
+        let project = ctx.client.project(&project_id).await?;
+        if config.contains_mod(&project.slug)
+            || config.dependencies.contains(&project.slug)
+            || manifest.installed.contains_key(&project.slug)
+        {
+            continue;
+        }
+
+        status!(ctx, "Installing required dependency '{}'", project.slug);
+        let tiers = config.loader_tiers(ProjectType::Mod, &config.version);
+        let (installed_mod, dependencies) = if let Some(version) = version {
+            (
+                download_version(ctx.clone(), &version, config.mods_dir(), &tiers).await?,
+                version.dependencies,
+            )
+        } else {
+            let (_name, installed_mod, dependencies) = download_mod(
+                ctx.clone(),
+                project.slug.clone(),
+                tiers,
+                config.version.clone(),
+                config.channel(&project.slug),
+                true,
+                config.mods_dir().to_path_buf(),
+            )
+            .await?;
+            (installed_mod, dependencies)
+        };
+
+        config.dependencies.push(project.slug.clone());
+        manifest
+            .installed
+            .insert(project.slug.clone(), installed_mod);
+        queue.extend(dependencies);
+        installed.push(project.slug);
+    }
+
+    Ok(installed)
+}
+
+/// Install a file, downloading it into the store first unless it's already there
+async fn download_file(
+    ctx: Context,
+    url: String,
+    path: PathBuf,
+    hashes: Hash,
+    size: u64,
+) -> Result<(), Error> {
+    if ctx.dry_run {
+        match ctx.store.as_ref() {
+            Some(store) if store.contains(&hashes.sha512).await? => {
+                status!(ctx, "Would install '{}' from the store", path.display());
+            }
+            _ => {
+                ctx.planned_download_bytes
+                    .fetch_add(size, Ordering::Relaxed);
+                status!(ctx, "Would download '{}' ({size} bytes)", path.display());
+            }
+        }
+        return Ok(());
+    }
+
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent).await?;
+    }
+
+    match ctx.store.clone() {
+        Some(store) => {
+            if !store.contains(&hashes.sha512).await? {
+                let temp = store.temp_path().await?;
+                fetch_file(&ctx, &url, &path, &temp, &hashes).await?;
+                store.insert(&temp, &hashes.sha512).await?;
+            }
+            store.install(&hashes.sha512, &path).await?;
+        }
+        None => fetch_file(&ctx, &url, &path, &path, &hashes).await?,
+    }
+
+    status!(ctx, "Wrote file '{}'...", path.display());
 
-    let bytes = res.bytes().await?;
+    Ok(())
+}
 
+/// Download `url` to `dest`, removing it again unless it matches `hashes`
+async fn fetch_file(
+    ctx: &Context,
+    url: &str,
+    path: &Path,
+    dest: &Path,
+    hashes: &Hash,
+) -> Result<(), Error> {
+    status!(ctx, "Downloading '{}'...", path.display());
     stdout().flush().await?;
-    let mut file = tokio::fs::File::create(path.clone()).await?;
+    let mut res = ctx.client.download(url).await?;
+
+    let mut file = tokio::fs::File::create(dest).await?;
+    let mut sha512 = Sha512::new();
+    let mut sha1 = Sha1::new();
+    let written: Result<(), Error> = async {
+        while let Some(chunk) = res.chunk().await? {
+            sha512.update(&chunk);
+            sha1.update(&chunk);
+            file.write_all(&chunk).await?;
+        }
+        file.flush().await?;
+        Ok(())
+    }
+    .await;
+    drop(file);
+
+    if let Err(err) = written {
+        remove_file(dest).await?;
+        return Err(err);
+    }
 
-    file.write_all(&bytes).await?;
-    println!("Wrote file '{}'...", path);
+    if format!("{:x}", sha512.finalize()) != hashes.sha512
+        || format!("{:x}", sha1.finalize()) != hashes.sha1
+    {
+        remove_file(dest).await?;
+        return Err(Error::HashMismatch(path.display().to_string()));
+    }
 
     Ok(())
 }
 
-async fn get_versions(
-    client: Client,
-    mod_name: String,
-    loader: Option<Loaders>,
-    game_version: Option<String>,
-) -> Result<Vec<Version>, Error> {
-    let request = client.get(format!(
-        "https://api.modrinth.com/v2/project/{mod_name}/version"
-    ));
-    let request = if let Some(loader) = loader {
-        request.query(&[("loaders", format!("[\"{loader}\"]"))])
+/// Path of a file in the mods folder `dir`
+fn mod_path(dir: &Path, file: &str) -> PathBuf {
+    // Keep 'sodium.jar' rather than './sodium.jar' when mods are in the pack folder
+    if dir == Path::new(".") {
+        PathBuf::from(file)
     } else {
-        request
-    };
-    let request = if let Some(game_version) = game_version {
-        request.query(&[("game_versions", format!("[\"{game_version}\"]"))])
-    } else {
-        request
-    };
-    let res = request.send().await?;
-    if res.status().is_success() {
-        Ok(res.json().await?)
-    } else if res.status().as_u16() == 404 {
-        Err(Error::NotFound)
-    } else {
-        Err(res.status().into())
+        dir.join(file)
     }
 }
 
-async fn compatible_versions(
-    client: Client,
-    mods: Vec<String>,
-    loader: Loaders,
-) -> Result<Vec<GameVersion>, Error> {
-    let game_versions = get_game_versions(client.clone()).await?;
+/// Hex encoded sha512 of a file on disk
+async fn hash_file(path: impl AsRef<Path>) -> Result<String, Error> {
+    let bytes = tokio::fs::read(path).await?;
+    Ok(format!("{:x}", Sha512::digest(&bytes)))
+}
 
-    let mut set = JoinSet::new();
+/// Game versions each mod supports with any of its loaders or their fallbacks, keyed by mod name
+///
+/// Uses bulk requests. Versions are only fetched for projects that also support other loaders
+/// or for mods that don't take every release channel, otherwise the game versions listed on the
+/// project are exact.
+async fn supported_game_versions(
+    ctx: Context,
+    config: &Config,
+) -> Result<HashMap<String, HashSet<String>>, Error> {
+    let mut supported: HashMap<String, HashSet<String>> = config
+        .mods
+        .iter()
+        .map(|entry| (entry.name.clone(), HashSet::new()))
+        .collect();
 
-    for m in mods.iter() {
-        set.spawn(get_versions(
-            client.clone(),
-            m.clone(),
-            Some(loader.clone()),
-            None,
-        ));
-    }
+    // Mods by project id, for the projects whose versions need to be checked
+    let mut entries: HashMap<String, &ModEntry> = HashMap::new();
+    let mut version_ids = Vec::new();
+    let ids: Vec<String> = config.mods.iter().map(|entry| entry.name.clone()).collect();
+    for project in ctx.client.projects(&ids).await? {
+        let Some(entry) = config
+            .mods
+            .iter()
+            .find(|entry| entry.name == project.slug || entry.name == project.id)
+        else {
+            continue;
+        };
 
-    let mut mods_supported_versions = Vec::new();
-    while let Some(res) = set.join_next().await {
-        mods_supported_versions.push(res??);
-    }
-
-    let mut version_support = HashMap::new();
-    for mod_supported_versions in mods_supported_versions {
-        let mut versions = Vec::new();
-        for version in mod_supported_versions {
-            for game_version in version.game_versions {
-                let game_version = game_versions
-                    .iter()
-                    .find(|x| x.version == game_version)
-                    .expect("Invalid game version");
-                if !versions.contains(game_version) {
-                    versions.push(game_version.clone());
-                }
-            }
-        }
-        for version in versions {
-            version_support
-                .entry(version)
-                .and_modify(|count| *count += 1)
-                .or_insert(1);
+        // The pack's own loaders don't depend on the game version, only the fallbacks do
+        let loaders = entry.project_type.loaders(&config.loader);
+        if config.channel(&entry.name) == Channel::Alpha
+            && project.loaders.iter().all(|l| loaders.contains(l))
+        {
+            supported.insert(
+                entry.name.clone(),
+                project.game_versions.into_iter().collect(),
+            );
+            continue;
         }
+
+        version_ids.extend(project.versions);
+        entries.insert(project.id, entry);
     }
 
-    let mut compatible_versions = Vec::new();
-    let mods_count = mods.len();
-    for (version, count) in version_support.iter() {
-        if *count == mods_count {
-            compatible_versions.push((*version).clone());
+    // Versions of all projects are fetched together instead of listing them per project
+    for version in ctx.client.versions(&version_ids).await? {
+        let Some(entry) = entries.get(&version.project_id) else {
+            continue;
+        };
+        if !config.channel(&entry.name).allows(&version) {
+            continue;
+        }
+        let Some(game_versions) = supported.get_mut(&entry.name) else {
+            continue;
+        };
+        for game_version in version.game_versions {
+            let loaders = config
+                .loader_tiers(entry.project_type, &game_version)
+                .concat();
+            if version.loaders.iter().any(|l| loaders.contains(l)) {
+                game_versions.insert(game_version);
+            }
         }
     }
-    compatible_versions.sort_by(|a, b| a.date.cmp(&b.date));
+
+    Ok(supported)
+}
+
+async fn compatible_versions(ctx: Context, config: &Config) -> Result<Vec<GameVersion>, Error> {
+    let game_versions = ctx.client.game_versions().await?;
+    let supported = supported_game_versions(ctx.clone(), config).await?;
+
+    let mut compatible_versions: Vec<GameVersion> = game_versions
+        .into_iter()
+        .filter(|game_version| {
+            supported
+                .values()
+                .all(|versions| versions.contains(&game_version.version))
+        })
+        .collect();
+    compatible_versions.sort_by_key(|a| a.date);
     compatible_versions.reverse();
     Ok(compatible_versions)
 }
 
+/// Download every mod that isn't installed yet and is used on the pack's side
 async fn download_mods(
-    client: Client,
-    config: Config,
+    ctx: Context,
+    mut config: Config,
     mut manifest: ModManifest,
-) -> Result<(), Error> {
+) -> Result<PackChanges, Error> {
     let mut set = JoinSet::new();
+    let mut changes = PackChanges::default();
+
+    let other_side = other_side_mods(&ctx, &config, &manifest).await?;
+    let missing: Vec<String> = config
+        .all_mods()
+        .filter(|m| !manifest.installed.contains_key(*m))
+        .cloned()
+        .collect();
+
+    for m in missing {
+        if let Some(mod_side) = other_side.get(&m) {
+            status!(ctx, "Skipping '{m}', it is only used on the {mod_side}");
+            changes.skipped.push(m);
+            continue;
+        }
 
-    for m in config.mods {
-        if !manifest.installed.contains_key(&m) {
-            set.spawn(download_mod(
-                client.clone(),
+        let project_type = config.project_type(&m);
+        let tiers = config.loader_tiers(project_type, &config.version);
+        let dir = config.content_dir(project_type)?.to_path_buf();
+        if let Some(pin) = config.pin(&m) {
+            set.spawn(download_pinned(
+                ctx.clone(),
                 m,
-                config.loader.clone(),
+                pin.to_string(),
+                tiers,
                 config.version.clone(),
-                true,
+                dir,
             ));
+            continue;
         }
+
+        let channel = config.channel(&m);
+        set.spawn(download_mod(
+            ctx.clone(),
+            m,
+            tiers,
+            config.version.clone(),
+            channel,
+            true,
+            dir,
+        ));
     }
 
+    let mut dependencies = Vec::new();
+    let mut installed = Vec::new();
     while let Some(res) = set.join_next().await {
-        let (name, installed_mod) = res??;
+        let (name, installed_mod, mod_dependencies) = res??;
+        installed.push(name.clone());
         manifest.installed.insert(name, installed_mod);
+        dependencies.extend(mod_dependencies);
     }
 
-    manifest.try_save().await?;
-
-    Ok(())
-}
-
-async fn update_mods(client: Client, config: Config) -> Result<(), Error> {
-    let mut set = JoinSet::new();
-
-    for m in config.mods {
-        set.spawn(update_mod(
-            client.clone(),
-            m.clone(),
-            config.loader.clone(),
-            config.version.clone(),
-        ));
-    }
+    let added = install_dependencies(ctx.clone(), &mut config, &mut manifest, dependencies).await?;
+    record_sides(ctx.clone(), &mut manifest).await?;
 
-    let mut updates = Vec::new();
-    while let Some(res) = set.join_next().await {
-        updates.push(res??);
+    if !ctx.dry_run {
+        config.try_save().await?;
+        manifest.try_save().await?;
     }
 
-    println!("The following updates have been completed:");
-    for update in updates {
-        println!("\t{update}");
+    for name in installed.iter().chain(added.iter()) {
+        changes
+            .installed
+            .insert(name.clone(), manifest.installed[name].clone());
     }
-    Ok(())
+    changes.added = added;
+    Ok(changes)
 }
 
-async fn update_mod(
-    client: Client,
+/// Download the version a mod is pinned to
+async fn download_pinned(
+    ctx: Context,
     mod_name: String,
-    loader: Loaders,
+    pin: String,
+    tiers: Vec<Vec<String>>,
     game_version: String,
-) -> Result<String, Error> {
-    let mut entries = read_dir("./").await?;
-    let versions = get_versions(
-        client.clone(),
-        mod_name.clone(),
-        Some(loader),
-        Some(game_version),
-    )
-    .await?;
+    dir: PathBuf,
+) -> Result<(String, InstalledMod, Vec<Dependency>), Error> {
+    let version = find_version(&ctx, &mod_name, &pin, &tiers, &game_version).await?;
+    let installed_mod = download_version(ctx.clone(), &version, &dir, &tiers).await?;
+    Ok((mod_name, installed_mod, version.dependencies))
+}
 
-    let mut exsiting = Vec::new();
-    let latest_file = &versions[0].files[0];
-    while let Some(entry) = entries.next_entry().await? {
-        if *entry.file_name() == *latest_file.filename {
-            return Ok(format!("'{mod_name}' is already up to date"));
-        }
+/// Version of a mod for the pack's loaders and game version with the given id, number or name
+async fn find_version(
+    ctx: &Context,
+    mod_name: &str,
+    version: &str,
+    tiers: &[Vec<String>],
+    game_version: &str,
+) -> Result<Version, Error> {
+    ctx.client
+        .project_versions_for(mod_name, &tiers.concat(), Some(game_version))
+        .await?
+        .into_iter()
+        .find(|v| v.id == version || v.version_number == version || v.name == version)
+        .ok_or_else(|| Error::VersionNotFound(mod_name.to_string(), version.to_string()))
+}
 
-        for version in &versions[1..] {
-            if *entry.file_name() == *version.files[0].filename {
-                exsiting.push(version.files[0].filename.clone());
-            }
+/// Side each of the projects is used on according to Modrinth, keyed by mod name
+async fn project_sides(ctx: Context, mods: &[String]) -> Result<HashMap<String, Side>, Error> {
+    let mut sides = HashMap::new();
+    for project in ctx.client.projects(mods).await? {
+        if let Some(name) = mods
+            .iter()
+            .find(|m| **m == project.slug || **m == project.id)
+        {
+            sides.insert(name.clone(), project.side());
         }
     }
+    Ok(sides)
+}
 
-    for file in exsiting {
-        println!("Removing {file}");
-        remove_file(file).await?;
+/// Mods of the pack that aren't used on the side it is installed on, with the side they are used on
+async fn other_side_mods(
+    ctx: &Context,
+    config: &Config,
+    manifest: &ModManifest,
+) -> Result<HashMap<String, Side>, Error> {
+    if config.side.is_none() {
+        return Ok(HashMap::new());
     }
 
-    download_file(
-        client.clone(),
-        latest_file.url.clone(),
-        latest_file.filename.clone(),
-    )
-    .await?;
+    let recorded = |m: &str| manifest.installed.get(m).and_then(|m| m.side);
+    let unknown: Vec<String> = config
+        .all_mods()
+        .filter(|m| recorded(m).is_none())
+        .cloned()
+        .collect();
+    let sides = if unknown.is_empty() {
+        HashMap::new()
+    } else {
+        project_sides(ctx.clone(), &unknown).await?
+    };
 
-    Ok(format!("Updated '{mod_name}' to '{}'", versions[0].name))
+    Ok(config
+        .all_mods()
+        .filter_map(|m| {
+            let side = recorded(m).or(sides.get(m).copied());
+            (!config.uses(m, side)).then(|| (m.clone(), config.side(m, side)))
+        })
+        .collect())
 }
 
-async fn upgrade_mods(client: Client, config: Config, manifest: ModManifest) -> Result<(), Error> {
-    let game_versions = get_game_versions(client.clone()).await?;
-
-    let current_version = config.version;
-    let current_version_index = game_versions
+/// Look up the side of installed mods that don't have one recorded yet
+async fn record_sides(ctx: Context, manifest: &mut ModManifest) -> Result<(), Error> {
+    let unknown: Vec<String> = manifest
+        .installed
         .iter()
-        .position(|x| x.version == current_version)
-        .expect("Invalid game version");
-    let compatible_versions =
-        compatible_versions(client.clone(), config.mods.clone(), config.loader.clone()).await?;
-
-    let compatible_versions: Vec<GameVersion> = compatible_versions
-        .into_iter()
-        .filter(|ver| {
-            game_versions
-                .iter()
-                .position(|x| x == ver)
-                .expect("Invalid game version")
-                < current_version_index
-        })
+        .filter(|(_name, installed_mod)| installed_mod.side.is_none())
+        .map(|(name, _installed_mod)| name.clone())
         .collect();
-
-    if compatible_versions.is_empty() {
-        println!("No compatible versions available to upgrade to");
+    if unknown.is_empty() {
         return Ok(());
     }
 
-    println!("Compatible game versions:");
-    for (i, version) in compatible_versions.iter().enumerate() {
-        println!("\t{i} - {version}");
+    for (name, side) in project_sides(ctx, &unknown).await? {
+        if let Some(installed_mod) = manifest.installed.get_mut(&name) {
+            installed_mod.side = Some(side);
+        }
     }
+    Ok(())
+}
 
-    println!("Select game version (0-{}):", compatible_versions.len() - 1);
-    let buffer = spawn_blocking(move || {
-        let mut buffer = String::new();
-        match stdin().read_line(&mut buffer) {
-            Ok(_) => Ok::<std::string::String, Error>(buffer),
-            Err(err) => Err(err.into()),
-        }
-    })
-    .await??;
-    let i: usize = if let Ok(i) = buffer.trim().parse() {
-        if i >= compatible_versions.len() {
-            return Err(Error::InvalidIndex);
+async fn install_mods(
+    ctx: Context,
+    config: Config,
+    manifest: ModManifest,
+    frozen: bool,
+) -> Result<PackChanges, Error> {
+    // Mods for the other side are never locked, so they don't need to be
+    let other_side = other_side_mods(&ctx, &config, &manifest).await?;
+    if frozen {
+        for m in config.all_mods().filter(|m| !other_side.contains_key(*m)) {
+            match manifest.installed.get(m) {
+                Some(installed_mod) if installed_mod.is_locked() => {}
+                _ => return Err(Error::NotLocked(m.clone())),
+            }
         }
-        i
-    } else {
-        return Err(Error::InvalidIndex);
-    };
+    }
 
-    let version = &compatible_versions[i];
+    let mut changes = PackChanges {
+        skipped: other_side.keys().cloned().collect(),
+        ..Default::default()
+    };
+    changes.skipped.sort();
+    for (name, installed_mod) in manifest.installed.iter() {
+        if !installed_mod.is_locked() || other_side.contains_key(name) {
+            continue;
+        }
 
-    // Move all .jar files to 'old' directory
-    if !try_exists("./old/").await? {
-        create_dir("./old/").await?;
-    }
+        let dir = config.content_dir(config.project_type(name))?;
+        let path = mod_path(dir, &installed_mod.file);
+        if try_exists(&path).await? && hash_file(&path).await? == installed_mod.sha512 {
+            continue;
+        }
 
-    for (_name, installed_mod) in manifest.installed.iter() {
-        copy(
-            ["./", &installed_mod.file].iter().collect::<PathBuf>(),
-            ["./old", &installed_mod.file].iter().collect::<PathBuf>(),
+        status!(ctx, "Installing '{name}' {}", installed_mod.version);
+        download_file(
+            ctx.clone(),
+            installed_mod.url.clone(),
+            path,
+            installed_mod.hashes(),
+            installed_mod.size as u64,
         )
         .await?;
+        changes
+            .installed
+            .insert(name.clone(), installed_mod.clone());
     }
 
-    let mut dir = read_dir("./").await?;
-    while let Some(entry) = dir.next_entry().await? {
+    if frozen {
+        return Ok(changes);
+    }
+
+    // Resolve anything that isn't locked yet
+    let resolved = download_mods(ctx.clone(), config, manifest).await?;
+    changes.installed.extend(resolved.installed);
+    changes.added = resolved.added;
+    Ok(changes)
+}
+
+async fn update_mods(
+    ctx: Context,
+    mut config: Config,
+    mut manifest: ModManifest,
+) -> Result<(), Error> {
+    let other_side = other_side_mods(&ctx, &config, &manifest).await?;
+    for (m, mod_side) in other_side.iter() {
+        status!(ctx, "Skipping '{m}', it is only used on the {mod_side}");
+    }
+    let mods: Vec<String> = config
+        .all_mods()
+        .filter(|m| config.pin(m).is_none() && !other_side.contains_key(*m))
+        .cloned()
+        .collect();
+
+    let locked = locked_mods(&manifest, &mods);
+    let latest_versions = latest_locked_versions(&ctx, &config, &locked).await?;
+
+    let mut set = JoinSet::new();
+    let mut updates: Vec<ModUpdate> = config
+        .mods
+        .iter()
+        .filter(|entry| entry.pin.is_some())
+        .map(|entry| ModUpdate {
+            name: entry.name.clone(),
+            status: UpdateStatus::Pinned,
+            from: manifest.installed.get(&entry.name).cloned(),
+            to: None,
+        })
+        .collect();
+    let mut dependencies = Vec::new();
+    let mut unlocked: Vec<&String> = mods
+        .iter()
+        .filter(|m| !locked.iter().any(|(name, _)| name == *m))
+        .collect();
+
+    for (name, installed_mod) in locked.iter() {
+        match latest_versions.get(&installed_mod.sha512) {
+            None => updates.push(ModUpdate {
+                name: name.clone(),
+                status: UpdateStatus::NotFound,
+                from: Some(installed_mod.clone()),
+                to: None,
+            }),
+            // The newest version isn't on the mod's channel, so look through all of its versions
+            Some(version) if !config.channel(name).allows(version) => unlocked.push(name),
+            Some(version) if version.id == installed_mod.version_id => {
+                updates.push(ModUpdate {
+                    name: name.clone(),
+                    status: UpdateStatus::UpToDate,
+                    from: Some(installed_mod.clone()),
+                    to: Some(version.clone()),
+                });
+                dependencies.extend(version.dependencies.clone());
+            }
+            Some(version) => {
+                let project_type = config.project_type(name);
+                set.spawn(replace_mod(
+                    ctx.clone(),
+                    name.clone(),
+                    installed_mod.clone(),
+                    version.clone(),
+                    config.content_dir(project_type)?.to_path_buf(),
+                    config.loader_tiers(project_type, &config.version),
+                ));
+            }
+        }
+    }
+
+    for m in unlocked {
+        let project_type = config.project_type(m);
+        set.spawn(update_mod(
+            ctx.clone(),
+            m.clone(),
+            manifest.installed.get(m).cloned(),
+            config.loader_tiers(project_type, &config.version),
+            config.version.clone(),
+            config.channel(m),
+            config.content_dir(project_type)?.to_path_buf(),
+        ));
+    }
+
+    // Every task runs to completion so mods.lock matches the files of the updates that succeeded
+    let mut first_error = None;
+    while let Some(res) = set.join_next().await {
+        match res.map_err(Error::from).and_then(|res| res) {
+            Ok((update, installed_mod, mod_dependencies)) => {
+                manifest
+                    .installed
+                    .insert(update.name.clone(), installed_mod);
+                updates.push(update);
+                dependencies.extend(mod_dependencies);
+            }
+            Err(err) => {
+                first_error.get_or_insert(err);
+            }
+        }
+    }
+    if let Some(err) = first_error {
+        if !ctx.dry_run {
+            manifest.try_save().await?;
+        }
+        return Err(err);
+    }
+
+    let installed =
+        install_dependencies(ctx.clone(), &mut config, &mut manifest, dependencies).await?;
+    for slug in installed {
+        updates.push(ModUpdate {
+            from: None,
+            to: None,
+            status: UpdateStatus::InstalledDependency,
+            name: slug,
+        });
+    }
+    record_sides(ctx.clone(), &mut manifest).await?;
+
+    if !ctx.dry_run {
+        config.try_save().await?;
+        manifest.try_save().await?;
+    }
+
+    if !ctx.output.is_text() {
+        return ctx.output.print(&updates);
+    }
+    if ctx.dry_run {
+        status!(ctx, "The following updates would be made:");
+    } else {
+        status!(ctx, "The following updates have been completed:");
+    }
+    for update in updates {
+        status!(ctx, "\t{}", update_text(&ctx, &config, &update));
+    }
+    Ok(())
+}
+
+/// Mods that have an exact version in 'mods.lock'
+fn locked_mods(manifest: &ModManifest, mods: &[String]) -> Vec<(String, InstalledMod)> {
+    mods.iter()
+        .filter_map(|m| {
+            manifest
+                .installed
+                .get(m)
+                .filter(|installed_mod| installed_mod.is_locked())
+                .map(|installed_mod| (m.clone(), installed_mod.clone()))
+        })
+        .collect()
+}
+
+/// Newest version of each locked mod for the pack's loaders and game version, keyed by the
+/// sha512 of its installed file
+async fn latest_locked_versions(
+    ctx: &Context,
+    config: &Config,
+    locked: &[(String, InstalledMod)],
+) -> Result<HashMap<String, Version>, Error> {
+    // Locked mods are looked up in a single request by the hash of their file. Content of each
+    // project type is published for different loaders, and mods without a version for the
+    // pack's own loader are looked up again with its fallbacks
+    let mut hashes: HashMap<ProjectType, Vec<String>> = HashMap::new();
+    for (name, installed_mod) in locked.iter() {
+        hashes
+            .entry(config.project_type(name))
+            .or_default()
+            .push(installed_mod.sha512.clone());
+    }
+    let mut latest_versions = HashMap::new();
+    for (project_type, mut hashes) in hashes {
+        for loaders in config.loader_tiers(project_type, &config.version) {
+            if hashes.is_empty() {
+                break;
+            }
+            let found = ctx
+                .client
+                .version_file_updates(
+                    &hashes,
+                    HashAlgorithm::Sha512,
+                    &loaders,
+                    std::slice::from_ref(&config.version),
+                )
+                .await?;
+            hashes.retain(|hash| !found.contains_key(hash));
+            latest_versions.extend(found);
+        }
+    }
+    Ok(latest_versions)
+}
+
+/// Line describing an update in text output
+fn update_text(ctx: &Context, config: &Config, update: &ModUpdate) -> String {
+    let name = &update.name;
+    let to = update.to.as_ref().map(|version| version.name.as_str());
+    match update.status {
+        UpdateStatus::Updated => match (&update.from, ctx.dry_run) {
+            (Some(from), true) => format!(
+                "Would update '{name}' from '{}' to '{}'",
+                from.version,
+                to.unwrap_or_default()
+            ),
+            (None, true) => format!("Would install '{name}' '{}'", to.unwrap_or_default()),
+            (_, false) => format!("Updated '{name}' to '{}'", to.unwrap_or_default()),
+        },
+        UpdateStatus::UpToDate => format!("'{name}' is already up to date"),
+        UpdateStatus::Pinned => format!(
+            "'{name}' is pinned to {}",
+            config.pin(name).unwrap_or_default()
+        ),
+        UpdateStatus::NotFound => format!(
+            "No version of '{name}' found for {} {}",
+            config.loader, config.version
+        ),
+        UpdateStatus::InstalledDependency => {
+            format!("Installed '{name}' as a required dependency")
+        }
+    }
+}
+
+/// Replace the installed file of a locked mod with a newer version
+async fn replace_mod(
+    ctx: Context,
+    mod_name: String,
+    installed_mod: InstalledMod,
+    version: Version,
+    dir: PathBuf,
+    tiers: Vec<Vec<String>>,
+) -> Result<(ModUpdate, InstalledMod, Vec<Dependency>), Error> {
+    let new_mod = swap_version(ctx.clone(), &version, Some(&installed_mod), &dir, &tiers).await?;
+
+    let dependencies = version.dependencies.clone();
+    Ok((
+        ModUpdate {
+            name: mod_name,
+            status: UpdateStatus::Updated,
+            from: Some(installed_mod),
+            to: Some(version),
+        },
+        new_mod,
+        dependencies,
+    ))
+}
+
+async fn update_mod(
+    ctx: Context,
+    mod_name: String,
+    installed_mod: Option<InstalledMod>,
+    tiers: Vec<Vec<String>>,
+    game_version: String,
+    channel: Channel,
+    dir: PathBuf,
+) -> Result<(ModUpdate, InstalledMod, Vec<Dependency>), Error> {
+    let versions = tiered_versions(&ctx, &mod_name, &tiers, &game_version, channel).await?;
+    let Some(version) = versions.first() else {
+        return Err(Error::NoVersionsFound);
+    };
+    let file = primary_file(version)?;
+
+    // Entries written by older versions only record the file name
+    let current = installed_mod.as_ref().is_some_and(|installed_mod| {
+        if installed_mod.is_locked() {
+            installed_mod.version_id == version.id
+        } else {
+            installed_mod.file == file.filename
+        }
+    });
+    if current && try_exists(mod_path(&dir, &file.filename)).await? {
+        let mut new_mod = InstalledMod::new(version, file);
+        new_mod.loader = chosen_loader(version, &tiers);
+        new_mod.side = installed_mod
+            .as_ref()
+            .and_then(|installed_mod| installed_mod.side);
+        return Ok((
+            ModUpdate {
+                name: mod_name,
+                status: UpdateStatus::UpToDate,
+                from: installed_mod,
+                to: Some(version.clone()),
+            },
+            new_mod,
+            version.dependencies.clone(),
+        ));
+    }
+
+    let new_mod = swap_version(ctx.clone(), version, installed_mod.as_ref(), &dir, &tiers).await?;
+    Ok((
+        ModUpdate {
+            name: mod_name,
+            status: UpdateStatus::Updated,
+            from: installed_mod,
+            to: Some(version.clone()),
+        },
+        new_mod,
+        version.dependencies.clone(),
+    ))
+}
+
+/// Install a version in place of an installed mod
+///
+/// The new file is downloaded and verified next to the old one before being moved into place,
+/// so the old file is only removed once the new one is installed.
+async fn swap_version(
+    ctx: Context,
+    version: &Version,
+    installed_mod: Option<&InstalledMod>,
+    dir: &Path,
+    tiers: &[Vec<String>],
+) -> Result<InstalledMod, Error> {
+    let file = primary_file(version)?;
+    let path = mod_path(dir, &file.filename);
+    if ctx.dry_run {
+        download_file(
+            ctx.clone(),
+            file.url.clone(),
+            path,
+            file.hashes.clone(),
+            file.size as u64,
+        )
+        .await?;
+    } else {
+        let part = mod_path(dir, &format!("{}.part", file.filename));
+        let downloaded = download_file(
+            ctx.clone(),
+            file.url.clone(),
+            part.clone(),
+            file.hashes.clone(),
+            file.size as u64,
+        )
+        .await;
+        if let Err(err) = downloaded {
+            if try_exists(&part).await? {
+                remove_file(&part).await?;
+            }
+            return Err(err);
+        }
+        rename(&part, &path).await?;
+    }
+
+    if let Some(installed_mod) = installed_mod.filter(|m| m.file != file.filename) {
+        let old_path = mod_path(dir, &installed_mod.file);
+        if try_exists(&old_path).await? {
+            if ctx.dry_run {
+                status!(ctx, "Would remove '{}'", old_path.display());
+            } else {
+                status!(ctx, "Removing {}", installed_mod.file);
+                remove_file(old_path).await?;
+            }
+        }
+    }
+
+    let mut new_mod = InstalledMod::new(version, file);
+    new_mod.loader = chosen_loader(version, tiers);
+    new_mod.side = installed_mod.and_then(|installed_mod| installed_mod.side);
+    Ok(new_mod)
+}
+
+async fn upgrade_mods(
+    ctx: Context,
+    config: Config,
+    mut manifest: ModManifest,
+    to: Option<String>,
+) -> Result<(), Error> {
+    let game_versions = ctx.client.game_versions().await?;
+
+    let mods_dir = config.mods_dir().to_path_buf();
+    let current_version = config.version.clone();
+    let current_version_index = game_versions
+        .iter()
+        .position(|x| x.version == current_version)
+        .expect("Invalid game version");
+    let compatible_versions = compatible_versions(ctx.clone(), &config).await?;
+
+    let mut compatible_versions: Vec<GameVersion> = compatible_versions
+        .into_iter()
+        .filter(|ver| {
+            game_versions
+                .iter()
+                .position(|x| x == ver)
+                .expect("Invalid game version")
+                < current_version_index
+        })
+        .collect();
+
+    // Pinned mods keep their version, so the pack can only move to game versions it supports
+    let mut pinned = Vec::new();
+    for entry in config.mods.iter().filter(|entry| entry.pin.is_some()) {
+        let Some(installed_mod) = manifest.installed.get(&entry.name) else {
+            continue;
+        };
+        if !installed_mod.is_locked() {
+            return Err(Error::NotLocked(entry.name.clone()));
+        }
+        pinned.push((entry, ctx.client.version(&installed_mod.version_id).await?));
+    }
+    compatible_versions.retain(|game_version| {
+        let blocking: Vec<_> = pinned
+            .iter()
+            .filter(|(_entry, version)| !version.game_versions.contains(&game_version.version))
+            .collect();
+        for (entry, version) in blocking.iter() {
+            status!(
+                ctx,
+                "'{}' is pinned to {}, which blocks upgrading to {game_version}",
+                entry.name,
+                version.version_number
+            );
+        }
+        blocking.is_empty()
+    });
+
+    if compatible_versions.is_empty() && to.is_none() {
+        status!(ctx, "No compatible versions available to upgrade to");
+        return ctx.output.print(&Upgrade {
+            from: current_version,
+            to: None,
+            compatible_versions,
+        });
+    }
+
+    let version = select_game_version(&ctx, &compatible_versions, to).await?;
+
+    // Move all .jar files to 'old' directory
+    let old_dir = mods_dir.join("old");
+    if !ctx.dry_run && !try_exists(&old_dir).await? {
+        create_dir(&old_dir).await?;
+    }
+
+    for (name, installed_mod) in manifest.installed.iter() {
+        if config.pin(name).is_some() {
+            continue;
+        }
+        let path = mod_path(
+            config.content_dir(config.project_type(name))?,
+            &installed_mod.file,
+        );
+        move_to_old(&ctx, &path, &old_dir).await?;
+    }
+
+    // Installed files have been moved already, except for pinned ones which are kept
+    let installed_files: HashSet<&str> = manifest
+        .installed
+        .values()
+        .map(|installed_mod| installed_mod.file.as_str())
+        .collect();
+    let mut dir = read_dir(&mods_dir).await?;
+    while let Some(entry) = dir.next_entry().await? {
+        let file_name = entry.file_name().into_string().unwrap();
         if entry.file_type().await?.is_file()
-            && entry.file_name().into_string().unwrap().ends_with(".jar")
+            && file_name.ends_with(".jar")
+            && !installed_files.contains(file_name.as_str())
         {
-            copy(
-                entry.path(),
-                format!("./old/{}", entry.file_name().into_string().unwrap()),
-            )
-            .await?;
-            remove_file(entry.path()).await?;
+            move_to_old(&ctx, &entry.path(), &old_dir).await?;
         }
     }
 
@@ -504,210 +1501,1103 @@ async fn upgrade_mods(client: Client, config: Config, manifest: ModManifest) ->
         ..config
     };
 
-    download_mods(client.clone(), new_config.clone(), manifest).await?;
+    // Every locked version was for the old game version, except the pinned ones
+    manifest
+        .installed
+        .retain(|name, _installed_mod| new_config.pin(name).is_some());
+    download_mods(ctx.clone(), new_config.clone(), manifest).await?;
 
-    new_config.try_save().await?;
+    if ctx.dry_run {
+        status!(ctx, "Would upgrade pack to {version}");
+    } else {
+        new_config.try_save().await?;
+    }
 
-    Ok(())
+    ctx.output.print(&Upgrade {
+        from: current_version,
+        to: Some(version.to_string()),
+        compatible_versions,
+    })
 }
 
-async fn get_game_versions(client: Client) -> Result<Vec<GameVersion>, Error> {
-    let request = client.get("https://api.modrinth.com/v2/tag/game_version");
-    let res = request.send().await?;
+/// Game version to upgrade to, `to` if given or else prompted for
+async fn select_game_version(
+    ctx: &Context,
+    compatible_versions: &[GameVersion],
+    to: Option<String>,
+) -> Result<GameVersion, Error> {
+    let candidates = || {
+        compatible_versions
+            .iter()
+            .map(|version| version.to_string())
+            .collect()
+    };
+    if let Some(to) = to {
+        return compatible_versions
+            .iter()
+            .find(|version| version.version == to)
+            .cloned()
+            .ok_or_else(|| Error::UpgradeUnavailable(to, candidates()));
+    }
+    if compatible_versions.len() == 1 && ctx.non_interactive {
+        return Ok(compatible_versions[0].clone());
+    }
+    if ctx.non_interactive {
+        return Err(Error::Ambiguous(
+            "a game version to upgrade to".to_string(),
+            candidates(),
+        ));
+    }
+
+    status!(ctx, "Compatible game versions:");
+    for (i, version) in compatible_versions.iter().enumerate() {
+        status!(ctx, "\t{i} - {version}");
+    }
 
-    if res.status().is_success() {
-        Ok(res.json().await?)
+    status!(
+        ctx,
+        "Select game version (0-{}):",
+        compatible_versions.len() - 1
+    );
+    let buffer = spawn_blocking(move || {
+        let mut buffer = String::new();
+        match stdin().read_line(&mut buffer) {
+            Ok(_) => Ok::<std::string::String, Error>(buffer),
+            Err(err) => Err(err.into()),
+        }
+    })
+    .await??;
+    let i: usize = if let Ok(i) = buffer.trim().parse() {
+        if i >= compatible_versions.len() {
+            return Err(Error::InvalidIndex);
+        }
+        i
     } else {
-        Err(Error::StatusCode(res.status()))
+        return Err(Error::InvalidIndex);
+    };
+
+    Ok(compatible_versions[i].clone())
+}
+
+/// Move a file into the 'old' folder
+async fn move_to_old(ctx: &Context, path: &Path, old_dir: &Path) -> Result<(), Error> {
+    let dest = old_dir.join(path.file_name().unwrap_or_default());
+    if ctx.dry_run {
+        status!(
+            ctx,
+            "Would move '{}' to '{}'",
+            path.display(),
+            dest.display()
+        );
+        return Ok(());
     }
+    copy(path, dest).await?;
+    remove_file(path).await?;
+    Ok(())
 }
 
-async fn pack_init(loader: Loaders, game_version: String) -> Result<(), Error> {
+async fn pack_init(
+    ctx: &Context,
+    loader: Loaders,
+    game_version: String,
+    mods_dir: Option<PathBuf>,
+) -> Result<(), Error> {
     let mut config = Config {
-        loader,
-        version: game_version,
-        mods: Vec::new(),
+        mods_dir,
+        ..Config::new(loader, game_version)
     };
     config.try_save().await?;
-    println!("Created pack config 'mods.yaml'");
-    Ok(())
+    status!(ctx, "Created pack config 'mods.yaml'");
+    ctx.output.print(&config)
 }
 
 async fn add_mod(
-    client: Client,
+    ctx: Context,
     mut config: Config,
     mut manifest: ModManifest,
     mod_name: String,
+    project_type: ProjectType,
 ) -> Result<(), Error> {
-    let request = client.get("https://api.modrinth.com/v2/search").query(&[
-        ("query", mod_name.as_str()),
-        (
-            "facets",
-            format!(
-                "[[\"project_type:mod\"], [\"versions:{}\"], [\"categories:{}\"]]",
-                config.version, config.loader
-            )
-            .as_str(),
-        ),
-        ("limit", "5"),
-    ]);
-    let res = request.send().await?;
-    let mod_slug = if res.status().is_success() {
-        let search_result: SearchResult = res.json().await?;
-        if search_result.hits.is_empty() {
-            return Err(Error::NotFound);
-        } else if search_result.hits.len() == 1 {
-            search_result.hits[0].slug.clone()
-        } else {
-            for (i, hit) in search_result.hits.iter().enumerate() {
-                println!("\t{i} - {}: {}", hit.title, hit.description);
-            }
+    let tiers = config.loader_tiers(project_type, &config.version);
+    let facets = [
+        vec![format!("project_type:{project_type}")],
+        vec![format!("versions:{}", config.version)],
+        tiers
+            .concat()
+            .iter()
+            .map(|l| format!("categories:{l}"))
+            .collect(),
+    ];
+    let search_result = ctx.client.search(&mod_name, &facets, 5).await?;
+    let mod_slug = if search_result.hits.is_empty() {
+        return Err(Error::NotFound);
+    } else if search_result.hits.len() == 1 {
+        search_result.hits[0].slug.clone()
+    } else if ctx.non_interactive {
+        search_result
+            .hits
+            .iter()
+            .find(|hit| hit.slug == mod_name)
+            .map(|hit| hit.slug.clone())
+            .ok_or_else(|| {
+                Error::Ambiguous(
+                    format!("a project for '{mod_name}'"),
+                    search_result
+                        .hits
+                        .iter()
+                        .map(|hit| hit.slug.clone())
+                        .collect(),
+                )
+            })?
+    } else {
+        for (i, hit) in search_result.hits.iter().enumerate() {
+            status!(ctx, "\t{i} - {}: {}", hit.title, hit.description);
+        }
 
-            println!("Select mod (0-{}):", search_result.hits.len() - 1);
-            let buffer = spawn_blocking(move || {
-                let mut buffer = String::new();
-                match stdin().read_line(&mut buffer) {
-                    Ok(_) => Ok::<std::string::String, Error>(buffer),
-                    Err(err) => Err(err.into()),
-                }
-            })
-            .await??;
-            let i: usize = if let Ok(i) = buffer.trim().parse() {
-                if i >= search_result.hits.len() {
-                    return Err(Error::InvalidIndex);
-                }
-                i
-            } else {
+        status!(ctx, "Select mod (0-{}):", search_result.hits.len() - 1);
+        let buffer = spawn_blocking(move || {
+            let mut buffer = String::new();
+            match stdin().read_line(&mut buffer) {
+                Ok(_) => Ok::<std::string::String, Error>(buffer),
+                Err(err) => Err(err.into()),
+            }
+        })
+        .await??;
+        let i: usize = if let Ok(i) = buffer.trim().parse() {
+            if i >= search_result.hits.len() {
                 return Err(Error::InvalidIndex);
-            };
-            search_result.hits[i].slug.clone()
-        }
-    } else if res.status().as_u16() == 400 {
-        println!("Invalid request");
-        println!("{}", res.text().await?);
-        return Err(Error::InvalidRequest);
-    } else {
-        return Err(res.status().into());
+            }
+            i
+        } else {
+            return Err(Error::InvalidIndex);
+        };
+        search_result.hits[i].slug.clone()
     };
 
-    if config.mods.contains(&mod_slug) {
-        println!("'{mod_slug}' already present in pack");
-        return Ok(());
+    if config.contains_mod(&mod_slug) {
+        status!(ctx, "'{mod_slug}' already present in pack");
+        return ctx.output.print(&PackChanges::default());
     }
 
-    let (_name, installed_mod) = download_mod(
-        client.clone(),
+    if config.dependencies.contains(&mod_slug) {
+        config.dependencies.retain(|m| *m != mod_slug);
+        config
+            .mods
+            .push(ModEntry::new(mod_slug.clone(), ProjectType::Mod));
+        if ctx.dry_run {
+            status!(ctx, "Would add '{mod_slug}'");
+        } else {
+            config.try_save().await?;
+            status!(ctx, "'{mod_slug}' added");
+        }
+        return ctx.output.print(&PackChanges {
+            added: vec![mod_slug],
+            ..Default::default()
+        });
+    }
+
+    let (_name, installed_mod, dependencies) = download_mod(
+        ctx.clone(),
         mod_slug.clone(),
-        config.loader.clone(),
+        tiers,
         config.version.clone(),
+        config.channel(&mod_slug),
         true,
+        config.content_dir(project_type)?.to_path_buf(),
     )
     .await?;
-    config.mods.push(mod_slug.clone());
-    config.try_save().await?;
+    config
+        .mods
+        .push(ModEntry::new(mod_slug.clone(), project_type));
     manifest.installed.insert(mod_slug.clone(), installed_mod);
+    let installed =
+        install_dependencies(ctx.clone(), &mut config, &mut manifest, dependencies).await?;
+    record_sides(ctx.clone(), &mut manifest).await?;
+    let mut added = vec![mod_slug.clone()];
+    added.extend(installed);
+    let changes = PackChanges {
+        installed: added
+            .iter()
+            .filter_map(|m| Some((m.clone(), manifest.installed.get(m)?.clone())))
+            .collect(),
+        added,
+        ..Default::default()
+    };
+    if ctx.dry_run {
+        status!(ctx, "Would add '{mod_slug}'");
+        for slug in &changes.added[1..] {
+            status!(ctx, "Would add '{slug}' as a required dependency");
+        }
+        return ctx.output.print(&changes);
+    }
+    config.try_save().await?;
     manifest.try_save().await?;
-    println!("'{mod_slug}' added");
-    Ok(())
+    status!(ctx, "'{mod_slug}' added");
+    for slug in &changes.added[1..] {
+        status!(ctx, "'{slug}' added as a required dependency");
+    }
+    ctx.output.print(&changes)
 }
 
 async fn remove_mod(
+    ctx: Context,
     mut config: Config,
     mut manifest: ModManifest,
     mod_name: String,
 ) -> Result<(), Error> {
-    if !config.mods.contains(&mod_name) {
-        println!("No mod '{mod_name}' in pack");
-        return Ok(());
+    if !config.contains_mod(&mod_name) && !config.dependencies.contains(&mod_name) {
+        status!(ctx, "No mod '{mod_name}' in pack");
+        return ctx.output.print(&PackChanges::default());
     }
 
-    config.mods.retain(|m| *m != mod_name);
+    let project_type = config.project_type(&mod_name);
+    config.mods.retain(|m| m.name != mod_name);
+    config.dependencies.retain(|m| *m != mod_name);
+    if let Some(installed_mod) = manifest.installed.remove(&mod_name) {
+        delete_mod_file(&ctx, &config, project_type, &installed_mod).await?;
+    }
 
-    if let Some(installed_mod) = manifest.installed.get(&mod_name) {
-        remove_file(&installed_mod.file).await?;
+    let unused = unused_dependencies(&ctx, &config, &manifest).await?;
+    for name in unused.iter() {
+        config.dependencies.retain(|m| m != name);
+        if let Some(installed_mod) = manifest.installed.remove(name) {
+            delete_mod_file(&ctx, &config, ProjectType::Mod, &installed_mod).await?;
+        }
     }
 
-    manifest.installed.remove(&mod_name);
+    let mut removed = vec![mod_name.clone()];
+    removed.extend(unused);
+    let changes = PackChanges {
+        removed,
+        ..Default::default()
+    };
+
+    if ctx.dry_run {
+        status!(ctx, "Would remove '{mod_name}' from pack");
+        for name in &changes.removed[1..] {
+            status!(
+                ctx,
+                "Would remove dependency '{name}', nothing requires it anymore"
+            );
+        }
+        return ctx.output.print(&changes);
+    }
 
     config.try_save().await?;
     manifest.try_save().await?;
 
-    println!("Mod '{mod_name}' removed from pack");
+    status!(ctx, "Mod '{mod_name}' removed from pack");
+    for name in &changes.removed[1..] {
+        status!(
+            ctx,
+            "Dependency '{name}' removed, nothing requires it anymore"
+        );
+    }
 
+    ctx.output.print(&changes)
+}
+
+/// Delete the installed file of a mod, if it's still there
+async fn delete_mod_file(
+    ctx: &Context,
+    config: &Config,
+    project_type: ProjectType,
+    installed_mod: &InstalledMod,
+) -> Result<(), Error> {
+    let path = mod_path(config.content_dir(project_type)?, &installed_mod.file);
+    if try_exists(&path).await? {
+        if ctx.dry_run {
+            status!(ctx, "Would remove '{}'", path.display());
+        } else {
+            remove_file(path).await?;
+        }
+    }
     Ok(())
 }
 
-async fn list_mods(config: Config) -> Result<(), Error> {
-    println!("Mods in pack:");
-    for m in config.mods {
-        println!("\t{m}");
+/// Dependencies that none of the mods left in the pack require, directly or through another
+/// dependency
+///
+/// What a mod requires is only known for locked versions, so nothing is considered unused while
+/// any mod in the pack isn't locked.
+async fn unused_dependencies(
+    ctx: &Context,
+    config: &Config,
+    manifest: &ModManifest,
+) -> Result<Vec<String>, Error> {
+    if config.dependencies.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut locked = Vec::new();
+    for m in config.all_mods() {
+        match manifest.installed.get(m) {
+            Some(installed_mod) if installed_mod.is_locked() => locked.push(installed_mod),
+            _ => return Ok(Vec::new()),
+        }
+    }
+
+    let version_ids: Vec<String> = locked.iter().map(|m| m.version_id.clone()).collect();
+    let mut requires: HashMap<String, Vec<String>> = HashMap::new();
+    for version in ctx.client.versions(&version_ids).await? {
+        for dependency in version.dependencies {
+            if dependency.dependency_type != "required" {
+                continue;
+            }
+            // Only dependencies on a version would need another request to find their project
+            let Some(project_id) = dependency.project_id else {
+                return Ok(Vec::new());
+            };
+            requires
+                .entry(version.project_id.clone())
+                .or_default()
+                .push(project_id);
+        }
+    }
+
+    let mut required = HashSet::new();
+    let mut queue: Vec<&String> = config
+        .mods
+        .iter()
+        .filter_map(|entry| manifest.installed.get(&entry.name))
+        .map(|installed_mod| &installed_mod.project_id)
+        .collect();
+    while let Some(project_id) = queue.pop() {
+        for dependency in requires.get(project_id).into_iter().flatten() {
+            if required.insert(dependency) {
+                queue.push(dependency);
+            }
+        }
+    }
+
+    Ok(config
+        .dependencies
+        .iter()
+        .filter(|m| !required.contains(&manifest.installed[*m].project_id))
+        .cloned()
+        .collect())
+}
+
+async fn pin_mod(
+    ctx: Context,
+    mut config: Config,
+    mut manifest: ModManifest,
+    mod_name: String,
+    version: Option<String>,
+) -> Result<(), Error> {
+    if !config.contains_mod(&mod_name) {
+        return Err(Error::NotInPack(mod_name));
+    }
+
+    let project_type = config.project_type(&mod_name);
+    let tiers = config.loader_tiers(project_type, &config.version);
+    let installed_mod = manifest.installed.get(&mod_name);
+    let version = match version {
+        Some(version) => find_version(&ctx, &mod_name, &version, &tiers, &config.version).await?,
+        None => match installed_mod {
+            Some(installed_mod) if installed_mod.is_locked() => {
+                ctx.client.version(&installed_mod.version_id).await?
+            }
+            _ => return Err(Error::NotLocked(mod_name)),
+        },
+    };
+
+    if installed_mod.is_none_or(|installed_mod| installed_mod.version_id != version.id) {
+        let dir = config.content_dir(project_type)?.to_path_buf();
+        let new_mod = swap_version(ctx.clone(), &version, installed_mod, &dir, &tiers).await?;
+        manifest.installed.insert(mod_name.clone(), new_mod);
+        record_sides(ctx.clone(), &mut manifest).await?;
+    }
+
+    if let Some(entry) = config.entry_mut(&mod_name) {
+        entry.pin = Some(version.version_number.clone());
+    }
+    let pin = Pin {
+        name: mod_name,
+        pin: Some(version.version_number.clone()),
+    };
+    if ctx.dry_run {
+        status!(
+            ctx,
+            "Would pin '{}' to {}",
+            pin.name,
+            version.version_number
+        );
+        return ctx.output.print(&pin);
+    }
+    config.try_save().await?;
+    manifest.try_save().await?;
+
+    status!(ctx, "'{}' pinned to {}", pin.name, version.version_number);
+    ctx.output.print(&pin)
+}
+
+async fn unpin_mod(ctx: Context, mut config: Config, mod_name: String) -> Result<(), Error> {
+    let pin = Pin {
+        name: mod_name.clone(),
+        pin: None,
+    };
+    match config.entry_mut(&mod_name) {
+        Some(entry) if entry.pin.is_some() => entry.pin = None,
+        Some(_) => {
+            status!(ctx, "'{mod_name}' isn't pinned");
+            return ctx.output.print(&pin);
+        }
+        None => return Err(Error::NotInPack(mod_name)),
+    }
+    if ctx.dry_run {
+        status!(ctx, "Would unpin '{mod_name}'");
+        return ctx.output.print(&pin);
+    }
+    config.try_save().await?;
+
+    status!(ctx, "'{mod_name}' unpinned");
+    ctx.output.print(&pin)
+}
+
+async fn list_mods(ctx: &Context, config: Config, manifest: ModManifest) -> Result<(), Error> {
+    if !ctx.output.is_text() {
+        let mods = config
+            .mods
+            .into_iter()
+            .map(|entry| ListedMod {
+                installed: manifest.installed.get(&entry.name).cloned(),
+                name: entry.name,
+                project_type: entry.project_type,
+                side: entry.side,
+                channel: entry.channel,
+                pin: entry.pin,
+            })
+            .collect();
+        let dependencies = config
+            .dependencies
+            .into_iter()
+            .map(|name| ListedMod {
+                installed: manifest.installed.get(&name).cloned(),
+                name,
+                project_type: ProjectType::Mod,
+                side: None,
+                channel: None,
+                pin: None,
+            })
+            .collect();
+        return ctx.output.print(&PackList { mods, dependencies });
+    }
+
+    status!(ctx, "Mods in pack:");
+    for entry in config.mods {
+        let mut notes = Vec::new();
+        if entry.project_type != ProjectType::Mod {
+            notes.push(entry.project_type.to_string());
+        }
+        if let Some(pin) = &entry.pin {
+            notes.push(format!("pinned to {pin}"));
+        }
+        if notes.is_empty() {
+            status!(ctx, "\t{}", entry.name);
+        } else {
+            status!(ctx, "\t{} ({})", entry.name, notes.join(", "));
+        }
+    }
+
+    if !config.dependencies.is_empty() {
+        status!(ctx, "Dependencies:");
+        for m in config.dependencies {
+            status!(ctx, "\t{m}");
+        }
     }
 
     Ok(())
 }
 
-async fn latest_game_version(client: Client, config: Config) -> Result<(), Error> {
-    let game_versions: Vec<String> = get_game_versions(client.clone())
+async fn latest_game_version(ctx: Context, config: Config) -> Result<(), Error> {
+    let game_versions: Vec<String> = ctx
+        .client
+        .game_versions()
         .await?
         .into_iter()
         .filter(|v| v.version_type == VersionType::Release)
         .map(|v| v.version)
         .collect();
 
-    let game_versions = Arc::new(game_versions);
+    let supported = supported_game_versions(ctx.clone(), &config).await?;
 
-    let mut set = JoinSet::new();
-
-    for m in config.mods {
-        set.spawn(get_latest_mod_game_version(
-            client.clone(),
-            m.clone(),
-            game_versions.clone(),
-            config.loader.clone(),
-        ));
+    let latest: Vec<LatestGameVersion> = config
+        .mods
+        .into_iter()
+        .map(|entry| LatestGameVersion {
+            // Game versions are listed newest first
+            game_version: game_versions
+                .iter()
+                .find(|v| supported[&entry.name].contains(*v))
+                .cloned(),
+            name: entry.name,
+        })
+        .collect();
+    if !ctx.output.is_text() {
+        return ctx.output.print(&latest);
     }
 
-    set.join_all().await;
+    for m in latest {
+        match m.game_version {
+            Some(game_version) => status!(ctx, "{} - {game_version}", m.name),
+            None => status!(ctx, "{} - no supported game versions", m.name),
+        }
+    }
 
     Ok(())
 }
 
-async fn get_latest_mod_game_version(
-    client: Client,
+/// Print the mods that have a newer version, returning whether there are any
+async fn outdated_mods(ctx: Context, config: Config, manifest: ModManifest) -> Result<bool, Error> {
+    let other_side = other_side_mods(&ctx, &config, &manifest).await?;
+    let mods: Vec<String> = config
+        .all_mods()
+        .filter(|m| config.pin(m).is_none() && !other_side.contains_key(*m))
+        .cloned()
+        .collect();
+    let locked = locked_mods(&manifest, &mods);
+    let latest_versions = latest_locked_versions(&ctx, &config, &locked).await?;
+
+    let mut outdated = Vec::new();
+    let mut join_set = JoinSet::new();
+    for m in mods.iter() {
+        let installed_mod = manifest.installed.get(m);
+        let latest = installed_mod
+            .filter(|installed_mod| installed_mod.is_locked())
+            .map(|installed_mod| latest_versions.get(&installed_mod.sha512));
+        match (installed_mod, latest) {
+            // No version for the pack's loaders and game version
+            (_, Some(None)) => {}
+            (Some(installed_mod), Some(Some(version))) if config.channel(m).allows(version) => {
+                if version.id != installed_mod.version_id {
+                    outdated.push(OutdatedMod {
+                        name: m.clone(),
+                        current: Some(installed_mod.version.clone()),
+                        current_published: None,
+                        latest: version.name.clone(),
+                        latest_published: published_date(version),
+                    });
+                }
+            }
+            // Mods that aren't locked, or whose newest version isn't on their channel
+            _ => {
+                join_set.spawn(outdated_mod(
+                    ctx.clone(),
+                    m.clone(),
+                    installed_mod.cloned(),
+                    config.loader_tiers(config.project_type(m), &config.version),
+                    config.version.clone(),
+                    config.channel(m),
+                ));
+            }
+        }
+    }
+    while let Some(res) = join_set.join_next().await {
+        outdated.extend(res??);
+    }
+
+    // Installed versions are looked up in a single request for their publish dates
+    let version_ids: Vec<String> = outdated
+        .iter()
+        .filter_map(|m| manifest.installed.get(&m.name))
+        .filter(|installed_mod| installed_mod.is_locked())
+        .map(|installed_mod| installed_mod.version_id.clone())
+        .collect();
+    if !version_ids.is_empty() {
+        let published: HashMap<String, String> = ctx
+            .client
+            .versions(&version_ids)
+            .await?
+            .iter()
+            .map(|version| (version.id.clone(), published_date(version)))
+            .collect();
+        for m in outdated.iter_mut() {
+            m.current_published = manifest
+                .installed
+                .get(&m.name)
+                .and_then(|installed_mod| published.get(&installed_mod.version_id))
+                .cloned();
+        }
+    }
+    outdated.sort_by(|a, b| a.name.cmp(&b.name));
+
+    if !ctx.output.is_text() {
+        ctx.output.print(&outdated)?;
+    } else if outdated.is_empty() {
+        status!(ctx, "All mods are up to date");
+    } else {
+        let rows: Vec<[&str; 5]> = outdated
+            .iter()
+            .map(|m| {
+                [
+                    m.name.as_str(),
+                    m.current.as_deref().unwrap_or("not installed"),
+                    m.current_published.as_deref().unwrap_or("-"),
+                    m.latest.as_str(),
+                    m.latest_published.as_str(),
+                ]
+            })
+            .collect();
+        let header = ["Mod", "Current", "Published", "Latest", "Published"];
+        let mut widths = header.map(str::len);
+        for row in rows.iter() {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.len());
+            }
+        }
+        for row in [header].iter().chain(rows.iter()) {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(widths)
+                .map(|(cell, width)| format!("{cell:width$}"))
+                .collect();
+            status!(ctx, "{}", cells.join("  ").trim_end());
+        }
+    }
+
+    Ok(!outdated.is_empty())
+}
+
+/// Latest version of a mod if it is newer than the installed one
+async fn outdated_mod(
+    ctx: Context,
     mod_name: String,
-    game_versions: Arc<Vec<String>>,
-    loader: Loaders,
-) -> Result<(), Error> {
-    let mod_versions = get_versions(client.clone(), mod_name.clone(), Some(loader), None).await?;
-    let mut mod_game_versions = HashSet::new();
-    for mod_version in mod_versions {
-        for game_version in mod_version.game_versions {
-            mod_game_versions.insert(game_version);
+    installed_mod: Option<InstalledMod>,
+    tiers: Vec<Vec<String>>,
+    game_version: String,
+    channel: Channel,
+) -> Result<Option<OutdatedMod>, Error> {
+    let versions = tiered_versions(&ctx, &mod_name, &tiers, &game_version, channel).await?;
+    let Some(latest) = versions.first() else {
+        return Ok(None);
+    };
+
+    // Entries written by older versions only record the file name
+    let current = match installed_mod {
+        Some(installed_mod) if installed_mod.is_locked() => {
+            if installed_mod.version_id == latest.id {
+                return Ok(None);
+            }
+            Some(installed_mod.version)
+        }
+        Some(installed_mod) => {
+            if installed_mod.file == primary_file(latest)?.filename {
+                return Ok(None);
+            }
+            Some(installed_mod.version)
+        }
+        None => None,
+    };
+
+    Ok(Some(OutdatedMod {
+        name: mod_name,
+        current,
+        current_published: None,
+        latest: latest.name.clone(),
+        latest_published: published_date(latest),
+    }))
+}
+
+/// Day a version was published on, e.g. '2025-01-01'
+fn published_date(version: &Version) -> String {
+    let date = version.date_published.as_str();
+    date.split_once('T')
+        .map_or(date, |(day, _)| day)
+        .to_string()
+}
+
+async fn export_pack(
+    config: Config,
+    manifest: ModManifest,
+    format: ExportFormat,
+    file: Option<PathBuf>,
+    name: Option<String>,
+    pack_version: String,
+    loader_version: Option<String>,
+) -> Result<Export, Error> {
+    let name = match name {
+        Some(name) => name,
+        None => std::env::current_dir()?
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "pack".to_string()),
+    };
+
+    match format {
+        ExportFormat::Mrpack => {
+            let loader = mrpack::loader_dependency(&config.loader)
+                .ok_or(Error::UnsupportedLoader(config.loader.clone()))?;
+            let loader_version = loader_version
+                .or(config.loader_version.clone())
+                .ok_or(Error::MissingLoaderVersion)?;
+
+            let mut files = Vec::new();
+            for m in config.all_mods() {
+                let installed_mod = match manifest.installed.get(m) {
+                    Some(installed_mod) if installed_mod.is_locked() => installed_mod,
+                    _ => return Err(Error::NotLocked(m.clone())),
+                };
+                // Paths in the index are relative to the instance, where mods are in 'mods'
+                let dir = match config.project_type(m) {
+                    ProjectType::Mod => Path::new("mods"),
+                    project_type => config.content_dir(project_type)?,
+                };
+                files.push(IndexFile {
+                    path: format!(
+                        "{}/{}",
+                        dir.to_string_lossy().replace('\\', "/"),
+                        installed_mod.file
+                    ),
+                    hashes: Hashes {
+                        sha1: installed_mod.sha1.clone(),
+                        sha512: installed_mod.sha512.clone(),
+                    },
+                    env: None,
+                    downloads: vec![installed_mod.url.clone()],
+                    file_size: installed_mod.size as u64,
+                });
+            }
+
+            let index = Index {
+                format_version: 1,
+                game: "minecraft".to_string(),
+                version_id: pack_version,
+                name: name.clone(),
+                summary: None,
+                files,
+                dependencies: BTreeMap::from([
+                    ("minecraft".to_string(), config.version.clone()),
+                    (loader.to_string(), loader_version),
+                ]),
+            };
+
+            let file = file.unwrap_or_else(|| PathBuf::from(format!("{name}.mrpack")));
+            let overrides = Path::new(mrpack::OVERRIDES_DIR);
+            let overrides = if try_exists(overrides).await? {
+                Some(overrides.to_path_buf())
+            } else {
+                None
+            };
+
+            let path = file.clone();
+            spawn_blocking(move || mrpack::write(&path, &index, overrides.as_deref())).await??;
+            Ok(Export { file })
+        }
+    }
+}
+
+async fn import_pack(ctx: Context, file: PathBuf) -> Result<(), Error> {
+    if try_exists(Config::CONFIG_PATH).await? {
+        return Err(Error::PackExists);
+    }
+
+    let path = file.clone();
+    let index = spawn_blocking(move || mrpack::read_index(&path)).await??;
+    if index.game != "minecraft" {
+        return Err(Error::InvalidMrpack(format!(
+            "unsupported game '{}'",
+            index.game
+        )));
+    }
+    let version = index
+        .dependencies
+        .get("minecraft")
+        .ok_or(Error::InvalidMrpack("no minecraft version".to_string()))?
+        .clone();
+    let (loader, loader_version) = index
+        .dependencies
+        .iter()
+        .find_map(|(key, version)| mrpack::dependency_loader(key).map(|l| (l, version.clone())))
+        .ok_or(Error::InvalidMrpack("no supported mod loader".to_string()))?;
+
+    for index_file in &index.files {
+        let path = Path::new(&index_file.path);
+        if !path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return Err(Error::InvalidMrpack(format!(
+                "invalid file path '{}'",
+                index_file.path
+            )));
         }
     }
-    let mut mod_game_versions: Vec<String> = mod_game_versions
+
+    let hashes = index
+        .files
+        .iter()
+        .filter(|index_file| mrpack::path_project_type(&index_file.path).is_some())
+        .map(|index_file| index_file.hashes.sha512.clone())
+        .collect::<Vec<_>>();
+    let versions = ctx
+        .client
+        .version_files(&hashes, HashAlgorithm::Sha512)
+        .await?;
+    let project_ids: HashSet<String> = versions.values().map(|v| v.project_id.clone()).collect();
+    let slugs: HashMap<String, String> = ctx
+        .client
+        .projects(&project_ids.into_iter().collect::<Vec<_>>())
+        .await?
         .into_iter()
-        .filter(|v| game_versions.contains(v))
+        .map(|project| (project.id, project.slug))
         .collect();
-    mod_game_versions.sort_by(|a, b| {
-        game_versions
-            .iter()
-            .position(|v| v == a)
-            .expect("Invalid game version")
-            .cmp(
-                &game_versions
-                    .iter()
-                    .position(|v| v == b)
-                    .expect("Invalid game version"),
-            )
-    });
-    println!(
-        "{} - {}",
-        mod_name,
-        mod_game_versions
+
+    // Paths in the index are relative to the instance, which is the pack folder
+    let mut config = Config {
+        loader_version: Some(loader_version),
+        mods_dir: Some(PathBuf::from("mods")),
+        ..Config::new(loader, version)
+    };
+    let mut manifest = ModManifest {
+        installed: BTreeMap::new(),
+    };
+    let mut untracked = Vec::new();
+    let mut set = JoinSet::new();
+
+    for index_file in index.files {
+        let path = Path::new(&index_file.path);
+        let tracked = mrpack::path_project_type(&index_file.path).and_then(|project_type| {
+            let version = versions.get(&index_file.hashes.sha512)?;
+            let file = version
+                .files
+                .iter()
+                .find(|file| file.hashes.sha512 == index_file.hashes.sha512)?;
+            let slug = slugs.get(&version.project_id)?;
+            Some((slug.clone(), project_type, InstalledMod::new(version, file)))
+        });
+
+        let is_tracked = tracked.is_some();
+        let dest = if let Some((slug, project_type, mut installed_mod)) = tracked {
+            installed_mod.file = path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or(installed_mod.file);
+            let dest = mod_path(config.content_dir(project_type)?, &installed_mod.file);
+            config.mods.push(ModEntry::new(slug.clone(), project_type));
+            manifest.installed.insert(slug, installed_mod);
+            dest
+        } else {
+            untracked.push(index_file.path.clone());
+            PathBuf::from(&index_file.path)
+        };
+
+        let url = index_file
+            .downloads
             .first()
-            .expect("Mod has no supported game versions")
+            .ok_or(Error::InvalidMrpack(format!(
+                "no download for '{}'",
+                index_file.path
+            )))?
+            .clone();
+        let hashes = Hash {
+            sha512: index_file.hashes.sha512,
+            sha1: index_file.hashes.sha1,
+        };
+        if is_tracked {
+            set.spawn(download_file(
+                ctx.clone(),
+                url,
+                dest,
+                hashes,
+                index_file.file_size,
+            ));
+        } else {
+            // Untracked files aren't in 'mods.lock', so 'store gc' wouldn't keep them in the store
+            let ctx = ctx.clone();
+            set.spawn(async move {
+                if let Some(parent) = dest.parent() {
+                    create_dir_all(parent).await?;
+                }
+                fetch_file(&ctx, &url, &dest, &dest, &hashes).await?;
+                status!(ctx, "Wrote file '{}'...", dest.display());
+                Ok(())
+            });
+        }
+    }
+
+    while let Some(res) = set.join_next().await {
+        res??;
+    }
+
+    let overrides =
+        spawn_blocking(move || mrpack::extract_overrides(&file, Path::new("./"))).await??;
+
+    config.try_save().await?;
+    manifest.try_save().await?;
+
+    status!(
+        ctx,
+        "Imported '{}' with {} mods and {} override files",
+        index.name,
+        config.mods.len(),
+        overrides.len()
     );
+    if !untracked.is_empty() {
+        status!(
+            ctx,
+            "The following files aren't tracked by the pack and won't be updated:"
+        );
+        for path in untracked.iter() {
+            status!(ctx, "\t{path}");
+        }
+    }
 
-    Ok(())
+    ctx.output.print(&PackChanges {
+        added: config.mods.into_iter().map(|entry| entry.name).collect(),
+        installed: manifest.installed,
+        untracked,
+        ..Default::default()
+    })
+}
+
+async fn adopt_mods(
+    ctx: Context,
+    mut config: Config,
+    mut manifest: ModManifest,
+) -> Result<(), Error> {
+    let tracked: HashSet<String> = manifest
+        .installed
+        .values()
+        .map(|installed_mod| installed_mod.file.clone())
+        .collect();
+
+    // Map hash to file name for every untracked jar
+    let mut jars = HashMap::new();
+    let mut dir = read_dir(config.mods_dir()).await?;
+    while let Some(entry) = dir.next_entry().await? {
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        if entry.file_type().await?.is_file()
+            && file_name.ends_with(".jar")
+            && !tracked.contains(&file_name)
+        {
+            jars.insert(hash_file(entry.path()).await?, file_name);
+        }
+    }
+
+    if jars.is_empty() {
+        status!(ctx, "No untracked jars found");
+        return ctx.output.print(&PackChanges::default());
+    }
+
+    let hashes: Vec<String> = jars.keys().cloned().collect();
+    let versions = ctx
+        .client
+        .version_files(&hashes, HashAlgorithm::Sha512)
+        .await?;
+    let project_ids: HashSet<String> = versions.values().map(|v| v.project_id.clone()).collect();
+    let slugs: HashMap<String, String> = ctx
+        .client
+        .projects(&project_ids.into_iter().collect::<Vec<_>>())
+        .await?
+        .into_iter()
+        .map(|project| (project.id, project.slug))
+        .collect();
+
+    let mut changes = PackChanges::default();
+    let mut adopted = Vec::new();
+    for (hash, file_name) in jars {
+        let found = versions.get(&hash).and_then(|version| {
+            let file = version
+                .files
+                .iter()
+                .find(|file| file.hashes.sha512 == hash)?;
+            let slug = slugs.get(&version.project_id)?;
+            Some((slug.clone(), InstalledMod::new(version, file)))
+        });
+
+        let Some((slug, mut installed_mod)) = found else {
+            changes.untracked.push(file_name);
+            continue;
+        };
+
+        installed_mod.file = file_name;
+        if !config.contains_mod(&slug) && !config.dependencies.contains(&slug) {
+            config
+                .mods
+                .push(ModEntry::new(slug.clone(), ProjectType::Mod));
+            changes.added.push(slug.clone());
+        }
+        adopted.push(format!("{slug} ({})", installed_mod.version));
+        changes
+            .installed
+            .insert(slug.clone(), installed_mod.clone());
+        manifest.installed.insert(slug, installed_mod);
+    }
+
+    config.try_save().await?;
+    manifest.try_save().await?;
+
+    if !adopted.is_empty() {
+        adopted.sort();
+        status!(ctx, "Added to pack:");
+        for m in adopted {
+            status!(ctx, "\t{m}");
+        }
+    }
+    if !changes.untracked.is_empty() {
+        changes.untracked.sort();
+        status!(ctx, "Not found on Modrinth:");
+        for file_name in changes.untracked.iter() {
+            status!(ctx, "\t{file_name}");
+        }
+    }
+
+    changes.added.sort();
+    ctx.output.print(&changes)
+}
+
+async fn clear_cache(ctx: &Context, cache: Option<Cache>) -> Result<(), Error> {
+    let dir = match cache {
+        Some(cache) => {
+            cache.clear().await?;
+            status!(ctx, "Cleared cache in '{}'", cache.dir().display());
+            Some(cache.dir().to_path_buf())
+        }
+        None => {
+            status!(ctx, "No cache folder found");
+            None
+        }
+    };
+    ctx.output.print(&ClearedCache { dir })
+}
+
+async fn store_gc(ctx: &Context) -> Result<(), Error> {
+    let Some(store) = &ctx.store else {
+        status!(ctx, "No store folder found");
+        return ctx.output.print(&StoreGc {
+            removed_files: 0,
+            freed_bytes: 0,
+            packs: Vec::new(),
+        });
+    };
+
+    let mut referenced = HashSet::new();
+    let mut packs = Vec::new();
+    for pack in store.packs().await? {
+        if !try_exists(pack.join(Config::CONFIG_PATH)).await? {
+            status!(
+                ctx,
+                "Forgetting '{}', it is no longer a pack",
+                pack.display()
+            );
+            continue;
+        }
+        let manifest = ModManifest::try_load_from(&pack).await?;
+        referenced.extend(manifest.installed.into_values().map(|m| m.sha512));
+        packs.push(pack);
+    }
+    store.set_packs(&packs).await?;
+
+    let (removed, freed) = store.gc(&referenced).await?;
+    status!(
+        ctx,
+        "Removed {removed} files ({} KiB) not used by any of {} known packs",
+        freed / 1024,
+        packs.len()
+    );
+    ctx.output.print(&StoreGc {
+        removed_files: removed,
+        freed_bytes: freed,
+        packs,
+    })
 }
@@ -1,10 +1,494 @@
-use std::fmt::{Display, Formatter};
+use std::{
+    collections::HashMap,
+    fmt::{Display, Formatter},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 use clap::ValueEnum;
-use serde::{Deserialize, Serialize};
+use reqwest::{
+    header::{ETAG, IF_NONE_MATCH},
+    Client, ClientBuilder, RequestBuilder, Response, StatusCode,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use time::OffsetDateTime;
+use tokio::{
+    sync::Semaphore,
+    task::JoinSet,
+    time::{sleep, sleep_until, Instant},
+};
 
-#[derive(Debug, Deserialize)]
+use crate::{
+    cache::{Cache, Entry},
+    Error,
+};
+
+pub const API_URL: &str = "https://api.modrinth.com/v2";
+pub const DEFAULT_MAX_CONCURRENCY: usize = 8;
+pub const DEFAULT_MAX_RETRIES: u32 = 5;
+
+const MAX_IDS_PER_REQUEST: usize = 200;
+const BACKOFF_BASE: Duration = Duration::from_millis(500);
+const BACKOFF_MAX: Duration = Duration::from_secs(30);
+
+/// Something worth telling the user about that doesn't fail a request
+#[derive(Debug)]
+pub enum Notice {
+    /// Modrinth reported the rate limit as used up, requests wait this long
+    RateLimited(Duration),
+    /// A response couldn't be written to the cache, it is fetched again next time
+    CacheWriteFailed(Error),
+}
+
+impl Display for Notice {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Notice::RateLimited(reset) => write!(
+                f,
+                "Modrinth rate limit reached, waiting {}s",
+                reset.as_secs()
+            ),
+            Notice::CacheWriteFailed(err) => write!(f, "Failed to write cache: {err:?}"),
+        }
+    }
+}
+
+type NoticeHandler = Arc<dyn Fn(Notice) + Send + Sync>;
+
+/// Typed client for the Modrinth v2 API
+///
+/// Clones share the same concurrency limit and rate limit state. Requests wait while
+/// Modrinth reports the rate limit as used up, and rate limited (429) or transient
+/// failures are retried with exponential backoff. With a [`Cache`], GET responses are
+/// stored on disk and revalidated once they expire. Offline, cached responses are used
+/// regardless of their age and anything else fails with [`Error::Offline`].
+#[derive(Clone)]
+pub struct ModrinthClient {
+    client: Client,
+    base_url: String,
+    max_retries: u32,
+    limiter: Arc<Semaphore>,
+    rate_limited_until: Arc<Mutex<Option<Instant>>>,
+    cache: Option<Cache>,
+    offline: bool,
+    on_notice: Option<NoticeHandler>,
+}
+
+impl ModrinthClient {
+    /// Modrinth requires a user agent that uniquely identifies the application
+    pub fn new(user_agent: &str) -> Result<Self, Error> {
+        Ok(Self::from_client(
+            ClientBuilder::new().user_agent(user_agent).build()?,
+        ))
+    }
+
+    pub fn from_client(client: Client) -> Self {
+        Self {
+            client,
+            base_url: API_URL.to_string(),
+            max_retries: DEFAULT_MAX_RETRIES,
+            limiter: Arc::new(Semaphore::new(DEFAULT_MAX_CONCURRENCY)),
+            rate_limited_until: Arc::new(Mutex::new(None)),
+            cache: None,
+            offline: false,
+            on_notice: None,
+        }
+    }
+
+    /// Use a different API instance, e.g. a staging server or mirror
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Limit how many requests are in flight at once
+    pub fn with_max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.limiter = Arc::new(Semaphore::new(max_concurrency.max(1)));
+        self
+    }
+
+    /// How often a rate limited or failed request is retried before giving up
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Store responses on disk and reuse them while they are fresh
+    pub fn with_cache(mut self, cache: Cache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    pub fn cache(&self) -> Option<&Cache> {
+        self.cache.as_ref()
+    }
+
+    /// Never connect to Modrinth
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    pub fn is_offline(&self) -> bool {
+        self.offline
+    }
+
+    /// Be told about [`Notice`]s, the client never prints anything itself
+    pub fn with_notice_handler(mut self, handler: impl Fn(Notice) + Send + Sync + 'static) -> Self {
+        self.on_notice = Some(Arc::new(handler));
+        self
+    }
+
+    fn notify(&self, notice: Notice) {
+        if let Some(on_notice) = &self.on_notice {
+            on_notice(notice);
+        }
+    }
+
+    pub async fn project(&self, id: &str) -> Result<Project, Error> {
+        self.get(&format!("project/{id}"), &[]).await
+    }
+
+    /// Projects by slug or id. Unknown projects are left out
+    pub async fn projects(&self, ids: &[String]) -> Result<Vec<Project>, Error> {
+        self.get_by_ids("projects", ids).await
+    }
+
+    /// Versions of a project, newest first
+    pub async fn project_versions(
+        &self,
+        id: &str,
+        loader: Option<&Loaders>,
+        game_version: Option<&str>,
+    ) -> Result<Vec<Version>, Error> {
+        let loaders = loader.map(Loaders::compatible).unwrap_or_default();
+        self.project_versions_for(id, &loaders, game_version).await
+    }
+
+    /// Versions of a project for any of `loaders` (e.g. "iris" and "optifine" for shaders),
+    /// newest first
+    pub async fn project_versions_for(
+        &self,
+        id: &str,
+        loaders: &[String],
+        game_version: Option<&str>,
+    ) -> Result<Vec<Version>, Error> {
+        let mut query = Vec::new();
+        if !loaders.is_empty() {
+            query.push(("loaders", serde_json::to_string(loaders)?));
+        }
+        if let Some(game_version) = game_version {
+            query.push(("game_versions", format!("[\"{game_version}\"]")));
+        }
+        self.get(&format!("project/{id}/version"), &query).await
+    }
+
+    pub async fn version(&self, id: &str) -> Result<Version, Error> {
+        self.get(&format!("version/{id}"), &[]).await
+    }
+
+    /// Versions by id. Unknown versions are left out
+    pub async fn versions(&self, ids: &[String]) -> Result<Vec<Version>, Error> {
+        self.get_by_ids("versions", ids).await
+    }
+
+    /// Version a file belongs to, identified by its hash
+    pub async fn version_file(
+        &self,
+        hash: &str,
+        algorithm: HashAlgorithm,
+    ) -> Result<Version, Error> {
+        self.get(
+            &format!("version_file/{hash}"),
+            &[("algorithm", algorithm.to_string())],
+        )
+        .await
+    }
+
+    /// Versions files belong to, keyed by hash. Unknown hashes are left out
+    pub async fn version_files(
+        &self,
+        hashes: &[String],
+        algorithm: HashAlgorithm,
+    ) -> Result<HashMap<String, Version>, Error> {
+        let request = self
+            .client
+            .post(self.url("version_files"))
+            .json(&serde_json::json!({ "hashes": hashes, "algorithm": algorithm.to_string() }));
+        self.json(request).await
+    }
+
+    /// Latest version matching `loaders` and `game_versions` of the projects files belong to,
+    /// keyed by hash
+    pub async fn version_file_updates(
+        &self,
+        hashes: &[String],
+        algorithm: HashAlgorithm,
+        loaders: &[String],
+        game_versions: &[String],
+    ) -> Result<HashMap<String, Version>, Error> {
+        let request = self
+            .client
+            .post(self.url("version_files/update"))
+            .json(&serde_json::json!({
+                "hashes": hashes,
+                "algorithm": algorithm.to_string(),
+                "loaders": loaders,
+                "game_versions": game_versions,
+            }));
+        self.json(request).await
+    }
+
+    /// Search projects, `facets` are AND-ed lists of OR-ed filters (e.g. "project_type:mod")
+    pub async fn search(
+        &self,
+        query: &str,
+        facets: &[Vec<String>],
+        limit: u32,
+    ) -> Result<SearchResult, Error> {
+        self.get(
+            "search",
+            &[
+                ("query", query.to_string()),
+                ("facets", serde_json::to_string(facets)?),
+                ("limit", limit.to_string()),
+            ],
+        )
+        .await
+    }
+
+    pub async fn game_versions(&self) -> Result<Vec<GameVersion>, Error> {
+        self.get("tag/game_version", &[]).await
+    }
+
+    pub async fn loaders(&self) -> Result<Vec<LoaderTag>, Error> {
+        self.get("tag/loader", &[]).await
+    }
+
+    /// Start downloading a file, e.g. [`File::url`]
+    pub async fn download(&self, url: &str) -> Result<Response, Error> {
+        let res = self.send(self.client.get(url)).await?;
+        if res.status().is_success() {
+            Ok(res)
+        } else {
+            Err(res.status().into())
+        }
+    }
+
+    /// Bulk endpoints take a JSON list of ids, split into chunks to keep URLs short
+    async fn get_by_ids<T: DeserializeOwned + Send + 'static>(
+        &self,
+        path: &'static str,
+        ids: &[String],
+    ) -> Result<Vec<T>, Error> {
+        let mut set = JoinSet::new();
+        for chunk in ids.chunks(MAX_IDS_PER_REQUEST) {
+            let client = self.clone();
+            let ids = serde_json::to_string(chunk)?;
+            set.spawn(async move { client.get::<Vec<T>>(path, &[("ids", ids)]).await });
+        }
+
+        let mut items = Vec::new();
+        while let Some(res) = set.join_next().await {
+            items.extend(res??);
+        }
+        Ok(items)
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/{path}", self.base_url)
+    }
+
+    async fn get<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+    ) -> Result<T, Error> {
+        let request = self.client.get(self.url(path)).query(query);
+        let cache = self
+            .cache
+            .as_ref()
+            .and_then(|cache| Some((cache, cache.ttl(path)?)));
+        match cache {
+            Some((cache, ttl)) => self.cached(cache, request, ttl).await,
+            None => self.json(request).await,
+        }
+    }
+
+    async fn json<T: DeserializeOwned>(&self, request: RequestBuilder) -> Result<T, Error> {
+        let res = self.send(request).await?;
+        match res.status() {
+            status if status.is_success() => Ok(res.json().await?),
+            status => Err(status_error(status)),
+        }
+    }
+
+    /// Serve a GET request from the cache while fresh, otherwise revalidate it with its 'ETag'
+    async fn cached<T: DeserializeOwned>(
+        &self,
+        cache: &Cache,
+        request: RequestBuilder,
+        ttl: Duration,
+    ) -> Result<T, Error> {
+        let url = request_url(&request)?;
+        let entry = cache.get(&url).await;
+        if let Some(entry) = entry
+            .as_ref()
+            .filter(|entry| self.offline || entry.is_fresh(ttl))
+        {
+            return Ok(serde_json::from_str(&entry.body)?);
+        }
+
+        let request = match entry.as_ref().and_then(|entry| entry.etag.as_deref()) {
+            Some(etag) => request.header(IF_NONE_MATCH, etag),
+            None => request,
+        };
+        let res = self.send(request).await?;
+        let entry = match (res.status(), entry) {
+            (StatusCode::NOT_MODIFIED, Some(mut entry)) => {
+                entry.touch();
+                entry
+            }
+            (status, _) if status.is_success() => {
+                let etag = res
+                    .headers()
+                    .get(ETAG)
+                    .and_then(|etag| etag.to_str().ok())
+                    .map(str::to_string);
+                Entry::new(url, etag, res.text().await?)
+            }
+            (status, _) => return Err(status_error(status)),
+        };
+
+        // A cache that can't be written shouldn't stop the command
+        if let Err(err) = cache.put(&entry).await {
+            self.notify(Notice::CacheWriteFailed(err));
+        }
+        Ok(serde_json::from_str(&entry.body)?)
+    }
+
+    /// Send a request, waiting for the rate limit and retrying transient failures
+    async fn send(&self, request: RequestBuilder) -> Result<Response, Error> {
+        if self.offline {
+            return Err(Error::Offline(request_url(&request)?));
+        }
+
+        let mut attempt = 0;
+        loop {
+            let res = {
+                let _permit = self
+                    .limiter
+                    .acquire()
+                    .await
+                    .expect("request limiter is never closed");
+                self.wait_for_rate_limit().await;
+                request
+                    .try_clone()
+                    .expect("requests don't stream their body")
+                    .send()
+                    .await
+            };
+
+            let retry_after = match &res {
+                Ok(res) if res.status() == StatusCode::TOO_MANY_REQUESTS => {
+                    let reset = rate_limit_reset(res);
+                    self.rate_limit(reset.unwrap_or(backoff(attempt)));
+                    Some(Duration::ZERO)
+                }
+                Ok(res) => {
+                    if header(res, "x-ratelimit-remaining") == Some(0) {
+                        if let Some(reset) = rate_limit_reset(res) {
+                            self.rate_limit(reset);
+                        }
+                    }
+                    res.status().is_server_error().then(|| backoff(attempt))
+                }
+                Err(err) if err.is_timeout() || err.is_connect() => Some(backoff(attempt)),
+                Err(_) => None,
+            };
+
+            match retry_after {
+                Some(delay) if attempt < self.max_retries => {
+                    attempt += 1;
+                    sleep(delay).await;
+                }
+                _ => return Ok(res?),
+            }
+        }
+    }
+
+    fn rate_limit(&self, reset: Duration) {
+        let until = Instant::now() + reset;
+        let mut rate_limited_until = self.rate_limited_until.lock().unwrap();
+        if rate_limited_until.is_none_or(|current| current < until) {
+            *rate_limited_until = Some(until);
+            drop(rate_limited_until);
+            self.notify(Notice::RateLimited(reset));
+        }
+    }
+
+    async fn wait_for_rate_limit(&self) {
+        let until = *self.rate_limited_until.lock().unwrap();
+        if let Some(until) = until {
+            sleep_until(until).await;
+        }
+    }
+}
+
+fn request_url(request: &RequestBuilder) -> Result<String, Error> {
+    let request = request
+        .try_clone()
+        .expect("requests don't stream their body")
+        .build()?;
+    Ok(request.url().to_string())
+}
+
+fn status_error(status: StatusCode) -> Error {
+    match status {
+        StatusCode::NOT_FOUND => Error::NotFound,
+        StatusCode::BAD_REQUEST => Error::InvalidRequest,
+        status => status.into(),
+    }
+}
+
+fn header(res: &Response, name: &str) -> Option<u64> {
+    res.headers().get(name)?.to_str().ok()?.parse().ok()
+}
+
+/// Time until the rate limit window resets, from 'X-Ratelimit-Reset' or 'Retry-After'
+fn rate_limit_reset(res: &Response) -> Option<Duration> {
+    header(res, "x-ratelimit-reset")
+        .or_else(|| header(res, "retry-after"))
+        .map(Duration::from_secs)
+}
+
+fn backoff(attempt: u32) -> Duration {
+    BACKOFF_BASE
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(BACKOFF_MAX)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum HashAlgorithm {
+    Sha1,
+    Sha512,
+}
+
+impl Display for HashAlgorithm {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let str = match self {
+            HashAlgorithm::Sha1 => "sha1",
+            HashAlgorithm::Sha512 => "sha512",
+        };
+        write!(f, "{str}")
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Version {
     pub name: String,
     pub version_number: String,
@@ -25,7 +509,18 @@ pub struct Version {
     pub files: Vec<File>,
 }
 
-#[derive(Debug, Deserialize)]
+impl Version {
+    /// Release channel the version was published to, anything unknown counts as alpha
+    pub fn channel(&self) -> Channel {
+        match self.version_type.as_str() {
+            "release" => Channel::Release,
+            "beta" => Channel::Beta,
+            _ => Channel::Alpha,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Dependency {
     pub version_id: Option<String>,
     pub project_id: Option<String>,
@@ -33,7 +528,7 @@ pub struct Dependency {
     pub dependency_type: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct File {
     pub hashes: Hash,
     pub url: String,
@@ -43,7 +538,7 @@ pub struct File {
     pub file_type: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Hash {
     pub sha512: String,
     pub sha1: String,
@@ -57,6 +552,56 @@ pub enum Loaders {
     NeoForge,
     Quilt,
     LiteLoader,
+    Paper,
+    Purpur,
+    Spigot,
+    Velocity,
+    #[value(name = "bungeecord")]
+    BungeeCord,
+}
+
+impl Loaders {
+    /// Modrinth loaders whose projects run on this loader, e.g. Paper also runs Spigot and
+    /// Bukkit plugins
+    pub fn compatible(&self) -> Vec<String> {
+        let loaders: &[&str] = match self {
+            Loaders::Paper => &["paper", "spigot", "bukkit"],
+            Loaders::Purpur => &["purpur", "paper", "spigot", "bukkit"],
+            Loaders::Spigot => &["spigot", "bukkit"],
+            loader => return vec![loader.to_string()],
+        };
+        loaders.iter().map(|loader| loader.to_string()).collect()
+    }
+
+    /// Loaders whose mods also run on this loader for a game version, in order of preference
+    pub fn fallbacks(&self, game_version: &str) -> Vec<Loaders> {
+        match self {
+            Loaders::Quilt => vec![Loaders::Fabric],
+            // NeoForge only stayed compatible with Forge mods on the version it forked from
+            Loaders::NeoForge if game_version == "1.20.1" => vec![Loaders::Forge],
+            _ => Vec::new(),
+        }
+    }
+
+    /// Modrinth loaders to look for mods with, the compatible loaders of this loader followed by
+    /// those of each fallback
+    pub fn tiers(&self, game_version: &str) -> Vec<Vec<String>> {
+        std::iter::once(self.compatible())
+            .chain(self.fallbacks(game_version).iter().map(Loaders::compatible))
+            .collect()
+    }
+
+    /// Server or proxy loaders, whose projects are plugins rather than mods
+    pub fn is_plugin_loader(&self) -> bool {
+        matches!(
+            self,
+            Loaders::Paper
+                | Loaders::Purpur
+                | Loaders::Spigot
+                | Loaders::Velocity
+                | Loaders::BungeeCord
+        )
+    }
 }
 
 impl Display for Loaders {
@@ -67,12 +612,111 @@ impl Display for Loaders {
             Loaders::NeoForge => "neoforge",
             Loaders::Quilt => "quilt",
             Loaders::LiteLoader => "liteloader",
+            Loaders::Paper => "paper",
+            Loaders::Purpur => "purpur",
+            Loaders::Spigot => "spigot",
+            Loaders::Velocity => "velocity",
+            Loaders::BungeeCord => "bungeecord",
         };
         write!(f, "{str}")
     }
 }
 
-#[derive(Deserialize, Hash, Clone, PartialEq)]
+/// Kind of content a project provides
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, Hash, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ProjectType {
+    #[default]
+    Mod,
+    Resourcepack,
+    Shader,
+    Datapack,
+}
+
+impl ProjectType {
+    /// Modrinth loaders content of this type is published for, given the pack's mod loader
+    pub fn loaders(&self, loader: &Loaders) -> Vec<String> {
+        match self {
+            ProjectType::Mod => loader.compatible(),
+            ProjectType::Resourcepack => vec!["minecraft".to_string()],
+            ProjectType::Shader => vec!["iris".to_string(), "optifine".to_string()],
+            ProjectType::Datapack => vec!["datapack".to_string()],
+        }
+    }
+}
+
+impl Display for ProjectType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let str = match self {
+            ProjectType::Mod => "mod",
+            ProjectType::Resourcepack => "resourcepack",
+            ProjectType::Shader => "shader",
+            ProjectType::Datapack => "datapack",
+        };
+        write!(f, "{str}")
+    }
+}
+
+/// Release channel of a version, from most to least stable
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Channel {
+    #[default]
+    Release,
+    Beta,
+    Alpha,
+}
+
+impl Channel {
+    /// Whether versions on this channel can be used, i.e. it is at least as stable as the
+    /// version's channel
+    pub fn allows(&self, version: &Version) -> bool {
+        version.channel() <= *self
+    }
+}
+
+impl Display for Channel {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let str = match self {
+            Channel::Release => "release",
+            Channel::Beta => "beta",
+            Channel::Alpha => "alpha",
+        };
+        write!(f, "{str}")
+    }
+}
+
+/// Where content is used, either side or both
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    #[default]
+    Both,
+    Client,
+    Server,
+}
+
+impl Side {
+    /// Whether content used on this side is installed on `side`
+    pub fn includes(&self, side: Side) -> bool {
+        *self == Side::Both || side == Side::Both || *self == side
+    }
+}
+
+impl Display for Side {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let str = match self {
+            Side::Both => "both",
+            Side::Client => "client",
+            Side::Server => "server",
+        };
+        write!(f, "{str}")
+    }
+}
+
+#[derive(Serialize, Deserialize, Hash, Clone, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum VersionType {
     Release,
@@ -81,7 +725,7 @@ pub enum VersionType {
     Beta,
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct GameVersion {
     pub version: String,
     pub version_type: VersionType,
@@ -110,6 +754,38 @@ impl Display for GameVersion {
     }
 }
 
+#[derive(Debug, Deserialize)]
+pub struct Project {
+    pub id: String,
+    pub slug: String,
+    pub title: String,
+    pub description: String,
+    pub project_type: String,
+    pub client_side: String,
+    pub server_side: String,
+    pub game_versions: Vec<String>,
+    pub loaders: Vec<String>,
+    pub versions: Vec<String>,
+}
+
+impl Project {
+    /// Side the project is used on, according to its `client_side` and `server_side` support
+    pub fn side(&self) -> Side {
+        match (self.client_side.as_str(), self.server_side.as_str()) {
+            (_, "unsupported") => Side::Client,
+            ("unsupported", _) => Side::Server,
+            _ => Side::Both,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LoaderTag {
+    pub icon: String,
+    pub name: String,
+    pub supported_project_types: Vec<String>,
+}
+
 #[derive(Deserialize)]
 pub struct SearchResult {
     pub hits: Vec<Hit>,
@@ -0,0 +1,162 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use zip::{write::SimpleFileOptions, ZipArchive, ZipWriter};
+
+use crate::{
+    modrinth::{Loaders, ProjectType},
+    Error,
+};
+
+pub const INDEX_PATH: &str = "modrinth.index.json";
+pub const OVERRIDES_DIR: &str = "overrides";
+
+/// Contents of 'modrinth.index.json' in a '.mrpack' archive
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Index {
+    pub format_version: u32,
+    pub game: String,
+    pub version_id: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    pub files: Vec<IndexFile>,
+    pub dependencies: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexFile {
+    pub path: String,
+    pub hashes: Hashes,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env: Option<Env>,
+    pub downloads: Vec<String>,
+    pub file_size: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Hashes {
+    pub sha1: String,
+    pub sha512: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Env {
+    pub client: String,
+    pub server: String,
+}
+
+/// Key used for a mod loader in the index `dependencies`
+pub fn loader_dependency(loader: &Loaders) -> Option<&'static str> {
+    match loader {
+        Loaders::Fabric => Some("fabric-loader"),
+        Loaders::Forge => Some("forge"),
+        Loaders::NeoForge => Some("neoforge"),
+        Loaders::Quilt => Some("quilt-loader"),
+        Loaders::LiteLoader
+        | Loaders::Paper
+        | Loaders::Purpur
+        | Loaders::Spigot
+        | Loaders::Velocity
+        | Loaders::BungeeCord => None,
+    }
+}
+
+/// Mod loader for a key in the index `dependencies`
+pub fn dependency_loader(key: &str) -> Option<Loaders> {
+    match key {
+        "fabric-loader" => Some(Loaders::Fabric),
+        "forge" => Some(Loaders::Forge),
+        "neoforge" => Some(Loaders::NeoForge),
+        "quilt-loader" => Some(Loaders::Quilt),
+        _ => None,
+    }
+}
+
+/// Project type of a file in the index, from the instance folder it is installed to
+pub fn path_project_type(path: &str) -> Option<ProjectType> {
+    match Path::new(path).components().next()?.as_os_str().to_str()? {
+        "mods" => Some(ProjectType::Mod),
+        "resourcepacks" => Some(ProjectType::Resourcepack),
+        "shaderpacks" => Some(ProjectType::Shader),
+        _ => None,
+    }
+}
+
+/// Write a '.mrpack' archive containing the index and everything under `overrides`
+pub fn write(path: &Path, index: &Index, overrides: Option<&Path>) -> Result<(), Error> {
+    let mut zip = ZipWriter::new(fs::File::create(path)?);
+    let options = SimpleFileOptions::default();
+
+    zip.start_file(INDEX_PATH, options)?;
+    zip.write_all(serde_json::to_string_pretty(index)?.as_bytes())?;
+
+    if let Some(overrides) = overrides {
+        let mut dirs = vec![PathBuf::new()];
+        while let Some(dir) = dirs.pop() {
+            for entry in fs::read_dir(overrides.join(&dir))? {
+                let entry = entry?;
+                let relative = dir.join(entry.file_name());
+                let name = Path::new(OVERRIDES_DIR)
+                    .join(&relative)
+                    .to_string_lossy()
+                    .replace('\\', "/");
+                if entry.file_type()?.is_dir() {
+                    zip.add_directory(name, options)?;
+                    dirs.push(relative);
+                } else {
+                    zip.start_file(name, options)?;
+                    zip.write_all(&fs::read(entry.path())?)?;
+                }
+            }
+        }
+    }
+
+    zip.finish()?;
+    Ok(())
+}
+
+pub fn read_index(path: &Path) -> Result<Index, Error> {
+    let mut zip = ZipArchive::new(fs::File::open(path)?)?;
+    let index = zip.by_name(INDEX_PATH)?;
+    Ok(serde_json::from_reader(index)?)
+}
+
+/// Extract everything under 'overrides/' into `dest`, returning the extracted files
+pub fn extract_overrides(path: &Path, dest: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut zip = ZipArchive::new(fs::File::open(path)?)?;
+    let mut extracted = Vec::new();
+
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i)?;
+        let Some(name) = entry.enclosed_name() else {
+            return Err(Error::InvalidMrpack(entry.name().to_string()));
+        };
+        let Ok(relative) = name.strip_prefix(OVERRIDES_DIR) else {
+            continue;
+        };
+        if relative.as_os_str().is_empty() {
+            continue;
+        }
+
+        let out = dest.join(relative);
+        if entry.is_dir() {
+            fs::create_dir_all(&out)?;
+        } else {
+            if let Some(parent) = out.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            std::io::copy(&mut entry, &mut fs::File::create(&out)?)?;
+            extracted.push(relative.to_path_buf());
+        }
+    }
+
+    Ok(extracted)
+}
@@ -0,0 +1,149 @@
+//! Results of commands printed with `--output json` or `--output yaml`
+//!
+//! Field names are kept stable so scripts can rely on them. Modrinth types are included as
+//! returned by the API, and installed mods as recorded in 'mods.lock'.
+
+use std::{collections::BTreeMap, path::PathBuf};
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::{
+    modrinth::{Channel, GameVersion, ProjectType, Side, Version},
+    Error, InstalledMod,
+};
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+    Yaml,
+}
+
+impl OutputFormat {
+    pub fn is_text(&self) -> bool {
+        *self == OutputFormat::Text
+    }
+
+    /// Print a result as JSON or YAML, text output is printed by each command instead
+    pub fn print<T: Serialize>(&self, value: &T) -> Result<(), Error> {
+        match self {
+            OutputFormat::Text => {}
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(value)?),
+            OutputFormat::Yaml => print!("{}", serde_yaml::to_string(value)?),
+        }
+        Ok(())
+    }
+}
+
+/// Result of the commands that change which mods a pack has installed: `pack download`,
+/// `install`, `add`, `remove`, `adopt` and `import`
+#[derive(Serialize, Debug, Default)]
+pub struct PackChanges {
+    /// Mods added to 'mods.yaml', including dependencies
+    pub added: Vec<String>,
+    /// Mods removed from 'mods.yaml', including dependencies nothing requires anymore
+    pub removed: Vec<String>,
+    /// Mods whose file was installed
+    pub installed: BTreeMap<String, InstalledMod>,
+    /// Mods left out because they are only used on the other side
+    pub skipped: Vec<String>,
+    /// Files that couldn't be identified on Modrinth
+    pub untracked: Vec<String>,
+}
+
+/// Result of `pack pin` and `pack unpin`
+#[derive(Serialize, Debug)]
+pub struct Pin {
+    pub name: String,
+    /// Version the mod is pinned to, none once it is unpinned
+    pub pin: Option<String>,
+}
+
+/// Result of `pack export`
+#[derive(Serialize, Debug)]
+pub struct Export {
+    pub file: PathBuf,
+}
+
+/// Result of `cache clear`
+#[derive(Serialize, Debug)]
+pub struct ClearedCache {
+    /// Cleared folder, none if there was no cache folder
+    pub dir: Option<PathBuf>,
+}
+
+/// Result of `store gc`
+#[derive(Serialize, Debug)]
+pub struct StoreGc {
+    pub removed_files: usize,
+    pub freed_bytes: u64,
+    pub packs: Vec<PathBuf>,
+}
+
+/// Result of `pack list`
+#[derive(Serialize, Debug)]
+pub struct PackList {
+    pub mods: Vec<ListedMod>,
+    pub dependencies: Vec<ListedMod>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ListedMod {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub project_type: ProjectType,
+    pub side: Option<Side>,
+    pub channel: Option<Channel>,
+    pub pin: Option<String>,
+    pub installed: Option<InstalledMod>,
+}
+
+/// Entry in the result of `pack latest-game-version`
+#[derive(Serialize, Debug)]
+pub struct LatestGameVersion {
+    pub name: String,
+    pub game_version: Option<String>,
+}
+
+/// Entry in the result of `pack update`
+#[derive(Serialize, Debug)]
+pub struct ModUpdate {
+    pub name: String,
+    pub status: UpdateStatus,
+    /// Version installed before the update
+    pub from: Option<InstalledMod>,
+    /// Version installed by the update, or that already was
+    pub to: Option<Version>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum UpdateStatus {
+    Updated,
+    UpToDate,
+    Pinned,
+    NotFound,
+    InstalledDependency,
+}
+
+/// Entry in the result of `pack outdated`
+#[derive(Serialize, Debug)]
+pub struct OutdatedMod {
+    pub name: String,
+    /// Installed version, none if the mod isn't installed
+    pub current: Option<String>,
+    pub current_published: Option<String>,
+    pub latest: String,
+    pub latest_published: String,
+}
+
+/// Result of `pack upgrade`
+#[derive(Serialize)]
+pub struct Upgrade {
+    pub from: String,
+    /// Selected game version, none if there was nothing to upgrade to
+    pub to: Option<String>,
+    pub compatible_versions: Vec<GameVersion>,
+}
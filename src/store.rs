@@ -0,0 +1,187 @@
+use std::{
+    collections::HashSet,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::Error;
+
+const PACKS_PATH: &str = "packs.yaml";
+
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Downloaded files stored on disk by their sha512, shared by every pack
+///
+/// Files are only added once their hashes have been verified, so anything in the store can be
+/// installed without downloading it again.
+#[derive(Clone, Debug)]
+pub struct Store {
+    dir: PathBuf,
+    link_mode: LinkMode,
+}
+
+/// How stored files are installed into a pack
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkMode {
+    /// Hardlink to the stored file, copying if the pack is on another file system
+    #[default]
+    Hardlink,
+    /// Symlink to the stored file
+    Symlink,
+    /// Copy the stored file
+    Copy,
+}
+
+impl Store {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            link_mode: LinkMode::default(),
+        }
+    }
+
+    pub fn with_link_mode(mut self, link_mode: LinkMode) -> Self {
+        self.link_mode = link_mode;
+        self
+    }
+
+    /// '<user data dir>/mod-updater/store', e.g. '~/.local/share/mod-updater/store' on Linux
+    pub fn default_dir() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("mod-updater").join("store"))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Where the file with a hex encoded sha512 is stored
+    ///
+    /// Hashes come from 'mods.lock' and imported packs, so anything but 128 lowercase hex digits
+    /// is rejected instead of being used as a path.
+    pub fn path(&self, sha512: &str) -> Result<PathBuf, Error> {
+        let valid = sha512.len() == 128
+            && sha512
+                .bytes()
+                .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b));
+        if !valid {
+            return Err(Error::InvalidHash(sha512.to_string()));
+        }
+        Ok(self.dir.join(&sha512[..2]).join(sha512))
+    }
+
+    pub async fn contains(&self, sha512: &str) -> Result<bool, Error> {
+        Ok(tokio::fs::try_exists(self.path(sha512)?).await?)
+    }
+
+    /// Unique path inside the store to download a file to before it is added
+    pub async fn temp_path(&self) -> Result<PathBuf, Error> {
+        let dir = self.dir.join("tmp");
+        tokio::fs::create_dir_all(&dir).await?;
+        let n = TEMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+        Ok(dir.join(format!("{}-{n}.part", std::process::id())))
+    }
+
+    /// Move a verified file into the store
+    pub async fn insert(&self, file: &Path, sha512: &str) -> Result<(), Error> {
+        let path = self.path(sha512)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::rename(file, path).await?;
+        Ok(())
+    }
+
+    /// Link or copy a stored file to `dest`, replacing whatever is there
+    pub async fn install(&self, sha512: &str, dest: &Path) -> Result<(), Error> {
+        let path = self.path(sha512)?;
+        if tokio::fs::symlink_metadata(dest).await.is_ok() {
+            tokio::fs::remove_file(dest).await?;
+        }
+
+        match self.link_mode {
+            LinkMode::Hardlink => {
+                if tokio::fs::hard_link(&path, dest).await.is_err() {
+                    tokio::fs::copy(&path, dest).await?;
+                }
+            }
+            LinkMode::Symlink => symlink(&std::path::absolute(&path)?, dest).await?,
+            LinkMode::Copy => {
+                tokio::fs::copy(&path, dest).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Pack folders that have installed files from the store
+    pub async fn packs(&self) -> Result<Vec<PathBuf>, Error> {
+        match tokio::fs::read_to_string(self.dir.join(PACKS_PATH)).await {
+            Ok(contents) => Ok(serde_yaml::from_str(&contents)?),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub async fn set_packs(&self, packs: &[PathBuf]) -> Result<(), Error> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+        tokio::fs::write(self.dir.join(PACKS_PATH), serde_yaml::to_string(packs)?).await?;
+        Ok(())
+    }
+
+    /// Remember a pack folder so its files are kept by [`Store::gc`]
+    pub async fn add_pack(&self, pack: &Path) -> Result<(), Error> {
+        let pack = std::path::absolute(pack)?;
+        let mut packs = self.packs().await?;
+        if !packs.contains(&pack) {
+            packs.push(pack);
+            packs.sort();
+            self.set_packs(&packs).await?;
+        }
+        Ok(())
+    }
+
+    /// Remove stored files whose sha512 isn't in `referenced` and any leftover downloads,
+    /// returning how many files and bytes were removed
+    pub async fn gc(&self, referenced: &HashSet<String>) -> Result<(usize, u64), Error> {
+        let mut removed = 0;
+        let mut freed = 0;
+
+        let mut dirs = match tokio::fs::read_dir(&self.dir).await {
+            Ok(dirs) => dirs,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok((0, 0)),
+            Err(err) => return Err(err.into()),
+        };
+        while let Some(dir) = dirs.next_entry().await? {
+            if !dir.file_type().await?.is_dir() {
+                continue;
+            }
+            let temp = dir.file_name() == "tmp";
+
+            let mut files = tokio::fs::read_dir(dir.path()).await?;
+            while let Some(file) = files.next_entry().await? {
+                let name = file.file_name().to_string_lossy().into_owned();
+                if temp || !referenced.contains(&name) {
+                    freed += file.metadata().await?.len();
+                    tokio::fs::remove_file(file.path()).await?;
+                    removed += 1;
+                }
+            }
+        }
+
+        Ok((removed, freed))
+    }
+}
+
+#[cfg(unix)]
+async fn symlink(original: &Path, link: &Path) -> std::io::Result<()> {
+    tokio::fs::symlink(original, link).await
+}
+
+#[cfg(windows)]
+async fn symlink(original: &Path, link: &Path) -> std::io::Result<()> {
+    tokio::fs::symlink_file(original, link).await
+}
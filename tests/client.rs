@@ -0,0 +1,269 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use common::{jar_contents, sha512, start, GAME_VERSIONS_ETAG};
+use mod_updater::cache::Cache;
+use mod_updater::modrinth::{HashAlgorithm, Loaders, ModrinthClient, Notice};
+use mod_updater::Error;
+use wiremock::matchers::path;
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+async fn client() -> (MockServer, ModrinthClient) {
+    let (server, api_url) = start().await;
+    let client = ModrinthClient::new("FloatyJellyfish/mod-updater-tests")
+        .unwrap()
+        .with_base_url(&api_url);
+    (server, client)
+}
+
+#[tokio::test]
+async fn fetches_projects_by_slug_and_id() {
+    let (_server, client) = client().await;
+
+    let project = client.project("alpha").await.unwrap();
+    assert_eq!(project.id, "AAAAAAAA");
+
+    let projects = client
+        .projects(&["AAAAAAAA".to_string(), "beta".to_string()])
+        .await
+        .unwrap();
+    let slugs: Vec<&str> = projects.iter().map(|p| p.slug.as_str()).collect();
+    assert_eq!(slugs, ["alpha", "beta"]);
+
+    assert!(matches!(
+        client.project("missing").await,
+        Err(Error::NotFound)
+    ));
+}
+
+#[tokio::test]
+async fn filters_project_versions() {
+    let (_server, client) = client().await;
+
+    let versions = client
+        .project_versions("alpha", Some(&Loaders::Fabric), Some("1.21.4"))
+        .await
+        .unwrap();
+    let ids: Vec<&str> = versions.iter().map(|v| v.id.as_str()).collect();
+    assert_eq!(ids, ["A0000002", "A0000001"]);
+
+    let versions = client
+        .project_versions("alpha", Some(&Loaders::Forge), None)
+        .await
+        .unwrap();
+    assert!(versions.is_empty());
+}
+
+#[tokio::test]
+async fn fetches_versions_by_id_and_hash() {
+    let (_server, client) = client().await;
+
+    let version = client.version("B0000001").await.unwrap();
+    assert_eq!(version.project_id, "BBBBBBBB");
+    assert_eq!(version.dependencies.len(), 1);
+
+    let versions = client
+        .versions(&["A0000001".to_string(), "A0000003".to_string()])
+        .await
+        .unwrap();
+    assert_eq!(versions.len(), 2);
+
+    let hash = sha512(&jar_contents("alpha-1.0.jar"));
+    let version = client
+        .version_file(&hash, HashAlgorithm::Sha512)
+        .await
+        .unwrap();
+    assert_eq!(version.id, "A0000001");
+
+    let versions = client
+        .version_files(
+            &[hash.clone(), "unknown".to_string()],
+            HashAlgorithm::Sha512,
+        )
+        .await
+        .unwrap();
+    assert_eq!(versions.len(), 1);
+    assert_eq!(versions[&hash].id, "A0000001");
+}
+
+#[tokio::test]
+async fn searches_and_lists_tags() {
+    let (_server, client) = client().await;
+
+    let result = client
+        .search("bet", &[vec!["project_type:mod".to_string()]], 5)
+        .await
+        .unwrap();
+    assert_eq!(result.hits.len(), 1);
+    assert_eq!(result.hits[0].slug, "beta");
+
+    let game_versions = client.game_versions().await.unwrap();
+    assert_eq!(game_versions[0].version, "1.21.5");
+
+    let loaders = client.loaders().await.unwrap();
+    assert!(loaders.iter().any(|l| l.name == "fabric"));
+}
+
+fn game_versions_body() -> serde_json::Value {
+    serde_json::json!([
+        { "version": "1.21.4", "version_type": "release", "date": "2024-12-03T00:00:00Z", "major": false },
+    ])
+}
+
+#[tokio::test]
+async fn waits_for_rate_limit_reset_and_retries() {
+    let server = MockServer::start().await;
+    Mock::given(path("/v2/tag/game_version"))
+        .respond_with(ResponseTemplate::new(429).insert_header("X-Ratelimit-Reset", "1"))
+        .up_to_n_times(1)
+        .with_priority(1)
+        .mount(&server)
+        .await;
+    Mock::given(path("/v2/tag/game_version"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(game_versions_body()))
+        .mount(&server)
+        .await;
+    let notices = Arc::new(Mutex::new(Vec::new()));
+    let handler_notices = notices.clone();
+    let client = ModrinthClient::new("FloatyJellyfish/mod-updater-tests")
+        .unwrap()
+        .with_base_url(&format!("{}/v2", server.uri()))
+        .with_notice_handler(move |notice| handler_notices.lock().unwrap().push(notice));
+
+    let start = Instant::now();
+    let game_versions = client.game_versions().await.unwrap();
+
+    assert_eq!(game_versions.len(), 1);
+    assert!(start.elapsed() >= Duration::from_secs(1));
+    assert_eq!(server.received_requests().await.unwrap().len(), 2);
+    assert!(matches!(
+        notices.lock().unwrap()[..],
+        [Notice::RateLimited(reset)] if reset == Duration::from_secs(1)
+    ));
+}
+
+#[tokio::test]
+async fn retries_server_errors_with_backoff() {
+    let server = MockServer::start().await;
+    Mock::given(path("/v2/tag/game_version"))
+        .respond_with(ResponseTemplate::new(503))
+        .up_to_n_times(2)
+        .with_priority(1)
+        .mount(&server)
+        .await;
+    Mock::given(path("/v2/tag/game_version"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(game_versions_body()))
+        .mount(&server)
+        .await;
+    let client = ModrinthClient::new("FloatyJellyfish/mod-updater-tests")
+        .unwrap()
+        .with_base_url(&format!("{}/v2", server.uri()));
+
+    assert!(client.game_versions().await.is_ok());
+    assert_eq!(server.received_requests().await.unwrap().len(), 3);
+
+    let client = client.with_max_retries(1);
+    Mock::given(path("/v2/project/alpha"))
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&server)
+        .await;
+    assert!(matches!(
+        client.project("alpha").await,
+        Err(Error::StatusCode(status)) if status.as_u16() == 503
+    ));
+}
+
+#[tokio::test]
+async fn limits_requests_in_flight() {
+    let server = MockServer::start().await;
+    Mock::given(path("/v2/tag/game_version"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(game_versions_body())
+                .set_delay(Duration::from_millis(200)),
+        )
+        .mount(&server)
+        .await;
+    let client = ModrinthClient::new("FloatyJellyfish/mod-updater-tests")
+        .unwrap()
+        .with_base_url(&format!("{}/v2", server.uri()))
+        .with_max_concurrency(1);
+
+    let start = Instant::now();
+    let mut set = tokio::task::JoinSet::new();
+    for _ in 0..3 {
+        let client = client.clone();
+        set.spawn(async move { client.game_versions().await });
+    }
+    while let Some(res) = set.join_next().await {
+        res.unwrap().unwrap();
+    }
+
+    assert!(start.elapsed() >= Duration::from_millis(600));
+}
+
+#[tokio::test]
+async fn serves_fresh_responses_from_cache() {
+    let (server, client) = client().await;
+    let cache_dir = tempfile::tempdir().unwrap();
+    let client = client.with_cache(Cache::new(cache_dir.path()));
+
+    let first = client.game_versions().await.unwrap();
+    let second = client.game_versions().await.unwrap();
+    // Searches aren't cached
+    client.search("alpha", &[], 5).await.unwrap();
+    client.search("alpha", &[], 5).await.unwrap();
+
+    assert_eq!(first.len(), second.len());
+    let paths: Vec<String> = server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|r| r.url.path().to_string())
+        .collect();
+    assert_eq!(paths, ["/v2/tag/game_version", "/v2/search", "/v2/search"]);
+}
+
+#[tokio::test]
+async fn reports_cache_write_failures_without_failing() {
+    let (_server, client) = client().await;
+    // A file where the cache folder should be can't be written to
+    let cache_file = tempfile::NamedTempFile::new().unwrap();
+    let notices = Arc::new(Mutex::new(Vec::new()));
+    let handler_notices = notices.clone();
+    let client = client
+        .with_cache(Cache::new(cache_file.path()))
+        .with_notice_handler(move |notice| handler_notices.lock().unwrap().push(notice));
+
+    let game_versions = client.game_versions().await.unwrap();
+
+    assert!(!game_versions.is_empty());
+    assert!(matches!(
+        notices.lock().unwrap()[..],
+        [Notice::CacheWriteFailed(_)]
+    ));
+}
+
+#[tokio::test]
+async fn revalidates_expired_responses_with_etag() {
+    let (server, client) = client().await;
+    let cache_dir = tempfile::tempdir().unwrap();
+    let client = client.with_cache(Cache::new(cache_dir.path()).with_ttl(Duration::ZERO));
+
+    client.game_versions().await.unwrap();
+    let versions = client.game_versions().await.unwrap();
+
+    assert_eq!(versions[0].version, "1.21.5");
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 2);
+    assert!(requests[0].headers.get("if-none-match").is_none());
+    assert_eq!(requests[1].headers["if-none-match"], GAME_VERSIONS_ETAG);
+
+    Cache::new(cache_dir.path()).clear().await.unwrap();
+    client.game_versions().await.unwrap();
+    let requests = server.received_requests().await.unwrap();
+    assert!(requests[2].headers.get("if-none-match").is_none());
+}
@@ -0,0 +1,497 @@
+//! A small in-memory stand-in for the Modrinth API, served locally with wiremock
+// Each test binary only uses some of the helpers
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::{Output, Stdio};
+
+use serde_json::{json, Value};
+use sha1::Sha1;
+use sha2::{Digest, Sha512};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use wiremock::matchers::any;
+use wiremock::{Mock, MockServer, Request, Respond, ResponseTemplate};
+
+pub const GAME_VERSIONS_ETAG: &str = "\"game-versions-1\"";
+
+pub struct FakeProject {
+    pub id: &'static str,
+    pub slug: &'static str,
+    pub project_type: &'static str,
+    pub server_side: &'static str,
+    pub versions: Vec<FakeVersion>,
+}
+
+pub struct FakeVersion {
+    pub id: &'static str,
+    pub name: &'static str,
+    pub game_versions: &'static [&'static str],
+    pub loaders: &'static [&'static str],
+    pub date: &'static str,
+    pub filename: &'static str,
+    pub dependencies: &'static [&'static str],
+    pub version_type: &'static str,
+    /// Serve bytes that don't match the advertised hashes
+    pub corrupt: bool,
+}
+
+impl FakeVersion {
+    fn new(
+        id: &'static str,
+        name: &'static str,
+        game_versions: &'static [&'static str],
+        date: &'static str,
+    ) -> Self {
+        Self {
+            id,
+            name,
+            game_versions,
+            loaders: &["fabric"],
+            date,
+            filename: name,
+            dependencies: &[],
+            version_type: "release",
+            corrupt: false,
+        }
+    }
+}
+
+/// Contents served for a jar
+pub fn jar_contents(filename: &str) -> Vec<u8> {
+    format!("{filename} jar contents").into_bytes()
+}
+
+pub fn sha512(bytes: &[u8]) -> String {
+    format!("{:x}", Sha512::digest(bytes))
+}
+
+pub fn sha1(bytes: &[u8]) -> String {
+    format!("{:x}", Sha1::digest(bytes))
+}
+
+/// Projects known to every test:
+/// - 'alpha' has two versions for 1.21.4 and one for 1.21.5
+/// - 'beta' supports 1.21.4 and 1.21.5 and requires 'alpha'
+/// - 'broken' serves a file that doesn't match its hash
+/// - 'glow' is a shader for Iris and OptiFine
+/// - 'sign' is a Spigot/Bukkit plugin
+/// - 'zoom' is a client-only mod with a newer beta for 1.21.4
+pub fn projects() -> Vec<FakeProject> {
+    vec![
+        FakeProject {
+            id: "AAAAAAAA",
+            slug: "alpha",
+            project_type: "mod",
+            server_side: "optional",
+            versions: vec![
+                FakeVersion::new("A0000003", "alpha-2.0.jar", &["1.21.5"], "2025-04-01"),
+                FakeVersion::new("A0000002", "alpha-1.1.jar", &["1.21.4"], "2025-01-01"),
+                FakeVersion::new("A0000001", "alpha-1.0.jar", &["1.21.4"], "2024-12-10"),
+            ],
+        },
+        FakeProject {
+            id: "BBBBBBBB",
+            slug: "beta",
+            project_type: "mod",
+            server_side: "optional",
+            versions: vec![FakeVersion {
+                dependencies: &["AAAAAAAA"],
+                ..FakeVersion::new(
+                    "B0000001",
+                    "beta-1.0.jar",
+                    &["1.21.4", "1.21.5"],
+                    "2025-01-05",
+                )
+            }],
+        },
+        FakeProject {
+            id: "CCCCCCCC",
+            slug: "broken",
+            project_type: "mod",
+            server_side: "optional",
+            versions: vec![FakeVersion {
+                corrupt: true,
+                ..FakeVersion::new("C0000001", "broken-1.0.jar", &["1.21.4"], "2025-01-05")
+            }],
+        },
+        FakeProject {
+            id: "DDDDDDDD",
+            slug: "glow",
+            project_type: "shader",
+            server_side: "optional",
+            versions: vec![FakeVersion {
+                loaders: &["iris", "optifine"],
+                ..FakeVersion::new(
+                    "D0000001",
+                    "glow-1.0.zip",
+                    &["1.21.4", "1.21.5"],
+                    "2025-02-01",
+                )
+            }],
+        },
+        FakeProject {
+            id: "EEEEEEEE",
+            slug: "sign",
+            project_type: "mod",
+            server_side: "optional",
+            versions: vec![FakeVersion {
+                loaders: &["bukkit", "spigot"],
+                ..FakeVersion::new("E0000001", "sign-1.0.jar", &["1.21.4"], "2025-02-10")
+            }],
+        },
+        FakeProject {
+            id: "FFFFFFFF",
+            slug: "zoom",
+            project_type: "mod",
+            server_side: "unsupported",
+            versions: vec![
+                FakeVersion {
+                    version_type: "beta",
+                    ..FakeVersion::new("F0000002", "zoom-1.1-beta.jar", &["1.21.4"], "2025-03-15")
+                },
+                FakeVersion::new("F0000001", "zoom-1.0.jar", &["1.21.4"], "2025-03-01"),
+            ],
+        },
+    ]
+}
+
+pub struct FakeModrinth {
+    base: String,
+    projects: Vec<FakeProject>,
+}
+
+impl FakeModrinth {
+    fn project(&self, id: &str) -> Option<&FakeProject> {
+        self.projects.iter().find(|p| p.id == id || p.slug == id)
+    }
+
+    fn version_json(&self, project: &FakeProject, version: &FakeVersion) -> Value {
+        let contents = jar_contents(version.filename);
+        json!({
+            "name": version.name,
+            "version_number": version.name,
+            "changelog": null,
+            "dependencies": version.dependencies.iter().map(|id| json!({
+                "version_id": null,
+                "project_id": id,
+                "file_name": null,
+                "dependency_type": "required",
+            })).collect::<Vec<_>>(),
+            "game_versions": version.game_versions,
+            "version_type": version.version_type,
+            "loaders": version.loaders,
+            "featured": false,
+            "status": "listed",
+            "requested_status": null,
+            "id": version.id,
+            "project_id": project.id,
+            "author_id": "author",
+            "date_published": format!("{}T00:00:00Z", version.date),
+            "downloads": 0,
+            "changelog_url": null,
+            "files": [{
+                "hashes": { "sha512": sha512(&contents), "sha1": sha1(&contents) },
+                "url": format!("{}/files/{}", self.base, version.filename),
+                "filename": version.filename,
+                "primary": true,
+                "size": contents.len(),
+                "file_type": null,
+            }],
+        })
+    }
+
+    fn project_json(&self, project: &FakeProject) -> Value {
+        let union = |field: fn(&FakeVersion) -> &'static [&'static str]| {
+            let mut values: Vec<&str> = project.versions.iter().flat_map(field).copied().collect();
+            values.sort();
+            values.dedup();
+            values
+        };
+        json!({
+            "id": project.id,
+            "slug": project.slug,
+            "title": project.slug,
+            "description": format!("The {} {}", project.slug, project.project_type),
+            "project_type": project.project_type,
+            "client_side": "required",
+            "server_side": project.server_side,
+            "game_versions": union(|v| v.game_versions),
+            "loaders": union(|v| v.loaders),
+            "versions": project.versions.iter().map(|v| v.id).collect::<Vec<_>>(),
+        })
+    }
+
+    fn find_version(&self, id: &str) -> Option<(&FakeProject, &FakeVersion)> {
+        self.projects.iter().find_map(|project| {
+            project
+                .versions
+                .iter()
+                .find(|version| version.id == id)
+                .map(|version| (project, version))
+        })
+    }
+}
+
+/// Parse a JSON array query parameter such as `loaders=["fabric"]`
+fn list_param(query: &HashMap<String, String>, name: &str) -> Option<Vec<String>> {
+    query
+        .get(name)
+        .map(|value| serde_json::from_str(value).expect("query parameter should be a JSON list"))
+}
+
+impl Respond for FakeModrinth {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let query: HashMap<String, String> = request.url.query_pairs().into_owned().collect();
+        let path = request.url.path();
+        let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+
+        match (request.method.as_str(), segments.as_slice()) {
+            ("GET", ["files", filename]) => {
+                let corrupt = self
+                    .projects
+                    .iter()
+                    .flat_map(|p| &p.versions)
+                    .any(|v| v.filename == *filename && v.corrupt);
+                let mut contents = jar_contents(filename);
+                if corrupt {
+                    contents.extend_from_slice(b"tampered");
+                }
+                ResponseTemplate::new(200).set_body_bytes(contents)
+            }
+            ("GET", ["v2", "tag", "game_version"]) => {
+                if request
+                    .headers
+                    .get("if-none-match")
+                    .is_some_and(|etag| etag == GAME_VERSIONS_ETAG)
+                {
+                    return ResponseTemplate::new(304);
+                }
+                ResponseTemplate::new(200)
+                    .insert_header("etag", GAME_VERSIONS_ETAG)
+                    .set_body_json(json!([
+                        { "version": "1.21.5", "version_type": "release", "date": "2025-03-25T00:00:00Z", "major": false },
+                        { "version": "1.21.4", "version_type": "release", "date": "2024-12-03T00:00:00Z", "major": false },
+                        { "version": "1.21.3", "version_type": "release", "date": "2024-10-23T00:00:00Z", "major": false },
+                    ]))
+            }
+            ("GET", ["v2", "project", id, "version"]) => {
+                let Some(project) = self.project(id) else {
+                    return ResponseTemplate::new(404);
+                };
+                let loaders = list_param(&query, "loaders");
+                let game_versions = list_param(&query, "game_versions");
+                let versions: Vec<Value> = project
+                    .versions
+                    .iter()
+                    .filter(|v| {
+                        loaders
+                            .as_ref()
+                            .is_none_or(|l| l.iter().any(|l| v.loaders.contains(&l.as_str())))
+                    })
+                    .filter(|v| {
+                        game_versions.as_ref().is_none_or(|g| {
+                            g.iter().any(|g| v.game_versions.contains(&g.as_str()))
+                        })
+                    })
+                    .map(|v| self.version_json(project, v))
+                    .collect();
+                ResponseTemplate::new(200).set_body_json(versions)
+            }
+            ("GET", ["v2", "project", id]) => match self.project(id) {
+                Some(project) => ResponseTemplate::new(200).set_body_json(self.project_json(project)),
+                None => ResponseTemplate::new(404),
+            },
+            ("GET", ["v2", "projects"]) => {
+                let ids = list_param(&query, "ids").unwrap_or_default();
+                let projects: Vec<Value> = ids
+                    .iter()
+                    .filter_map(|id| self.project(id))
+                    .map(|project| self.project_json(project))
+                    .collect();
+                ResponseTemplate::new(200).set_body_json(projects)
+            }
+            ("GET", ["v2", "version", id]) => match self.find_version(id) {
+                Some((project, version)) => {
+                    ResponseTemplate::new(200).set_body_json(self.version_json(project, version))
+                }
+                None => ResponseTemplate::new(404),
+            },
+            ("GET", ["v2", "versions"]) => {
+                let ids = list_param(&query, "ids").unwrap_or_default();
+                let versions: Vec<Value> = ids
+                    .iter()
+                    .filter_map(|id| self.find_version(id))
+                    .map(|(project, version)| self.version_json(project, version))
+                    .collect();
+                ResponseTemplate::new(200).set_body_json(versions)
+            }
+            ("GET", ["v2", "version_file", hash]) => {
+                let found = self.projects.iter().find_map(|project| {
+                    project
+                        .versions
+                        .iter()
+                        .find(|version| sha512(&jar_contents(version.filename)) == *hash)
+                        .map(|version| self.version_json(project, version))
+                });
+                match found {
+                    Some(version) => ResponseTemplate::new(200).set_body_json(version),
+                    None => ResponseTemplate::new(404),
+                }
+            }
+            ("GET", ["v2", "tag", "loader"]) => ResponseTemplate::new(200).set_body_json(json!([
+                { "icon": "<svg></svg>", "name": "fabric", "supported_project_types": ["mod", "modpack"] },
+                { "icon": "<svg></svg>", "name": "quilt", "supported_project_types": ["mod", "modpack"] },
+            ])),
+            ("POST", ["v2", "version_files"]) => {
+                let body: Value = request.body_json().expect("body should be JSON");
+                let hashes: Vec<String> =
+                    serde_json::from_value(body["hashes"].clone()).expect("hashes should be a list");
+                let mut found = serde_json::Map::new();
+                for project in &self.projects {
+                    for version in &project.versions {
+                        let hash = sha512(&jar_contents(version.filename));
+                        if hashes.contains(&hash) {
+                            found.insert(hash, self.version_json(project, version));
+                        }
+                    }
+                }
+                ResponseTemplate::new(200).set_body_json(found)
+            }
+            ("POST", ["v2", "version_files", "update"]) => {
+                let body: Value = request.body_json().expect("body should be JSON");
+                let list = |key: &str| -> Vec<String> {
+                    serde_json::from_value(body[key].clone()).expect("field should be a list")
+                };
+                let (hashes, loaders, game_versions) =
+                    (list("hashes"), list("loaders"), list("game_versions"));
+                let mut found = serde_json::Map::new();
+                for project in &self.projects {
+                    let latest = project.versions.iter().find(|v| {
+                        loaders.iter().any(|l| v.loaders.contains(&l.as_str()))
+                            && game_versions
+                                .iter()
+                                .any(|g| v.game_versions.contains(&g.as_str()))
+                    });
+                    for version in &project.versions {
+                        let hash = sha512(&jar_contents(version.filename));
+                        if let (true, Some(latest)) = (hashes.contains(&hash), latest) {
+                            found.insert(hash, self.version_json(project, latest));
+                        }
+                    }
+                }
+                ResponseTemplate::new(200).set_body_json(found)
+            }
+            ("GET", ["v2", "search"]) => {
+                let search = query.get("query").cloned().unwrap_or_default();
+                let facets: Vec<Vec<String>> = query
+                    .get("facets")
+                    .map(|facets| serde_json::from_str(facets).expect("facets should be JSON"))
+                    .unwrap_or_default();
+                let project_type = facets
+                    .iter()
+                    .flatten()
+                    .find_map(|facet| facet.strip_prefix("project_type:"));
+                let hits: Vec<Value> = self
+                    .projects
+                    .iter()
+                    .filter(|p| p.slug.contains(search.as_str()))
+                    .filter(|p| project_type.is_none_or(|t| t == p.project_type))
+                    .map(|p| {
+                        json!({
+                            "title": p.slug,
+                            "description": format!("The {} mod", p.slug),
+                            "slug": p.slug,
+                            "project_id": p.id,
+                            "author": "author",
+                            "display_categories": ["fabric"],
+                            "versions": ["1.21.4", "1.21.5"],
+                            "follows": 0,
+                            "date_created": "2024-01-01T00:00:00Z",
+                            "date_modified": "2025-01-01T00:00:00Z",
+                            "latest_version": "1.21.5",
+                            "license": "MIT",
+                            "gallery": [],
+                            "featured_gallery": null,
+                        })
+                    })
+                    .collect();
+                ResponseTemplate::new(200).set_body_json(json!({
+                    "hits": hits,
+                    "offset": 0,
+                    "limit": 5,
+                    "total_hits": hits.len(),
+                }))
+            }
+            _ => ResponseTemplate::new(404),
+        }
+    }
+}
+
+/// Start a fake Modrinth server, returning it with its API base URL
+pub async fn start() -> (MockServer, String) {
+    let server = MockServer::start().await;
+    Mock::given(any())
+        .respond_with(FakeModrinth {
+            base: server.uri(),
+            projects: projects(),
+        })
+        .mount(&server)
+        .await;
+    let api_url = format!("{}/v2", server.uri());
+    (server, api_url)
+}
+
+/// The CLI binary running in `dir`, without any API URL configured
+pub fn command(dir: &Path) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_mod-updater"));
+    command
+        .current_dir(dir)
+        .env_remove("MODRINTH_API_URL")
+        .env("MOD_UPDATER_CACHE_DIR", dir.join(".cache"))
+        .env("MOD_UPDATER_STORE_DIR", dir.join(".store"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    command
+}
+
+/// Run the CLI in `dir` against `api_url`, feeding `stdin` to it
+pub async fn run(dir: &Path, api_url: &str, args: &[&str], stdin: &str) -> Output {
+    let mut child = command(dir)
+        .args(args)
+        .arg("--api-url")
+        .arg(api_url)
+        .spawn()
+        .expect("failed to start mod-updater");
+    let mut child_stdin = child.stdin.take().unwrap();
+    child_stdin.write_all(stdin.as_bytes()).await.unwrap();
+    drop(child_stdin);
+    child.wait_with_output().await.unwrap()
+}
+
+/// Like [`run`] but panics with the output if the command fails
+pub async fn run_ok(dir: &Path, api_url: &str, args: &[&str]) -> String {
+    run_ok_with_stdin(dir, api_url, args, "").await
+}
+
+pub async fn run_ok_with_stdin(dir: &Path, api_url: &str, args: &[&str], stdin: &str) -> String {
+    let output = run(dir, api_url, args, stdin).await;
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    assert!(
+        output.status.success(),
+        "'{}' failed\nstdout:\n{stdout}\nstderr:\n{}",
+        args.join(" "),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    stdout
+}
+
+pub fn write(dir: &Path, path: &str, contents: &str) {
+    std::fs::write(dir.join(path), contents).unwrap();
+}
+
+pub fn read(dir: &Path, path: &str) -> String {
+    std::fs::read_to_string(dir.join(path)).unwrap()
+}
@@ -0,0 +1,1096 @@
+mod common;
+
+use std::path::Path;
+
+use common::{jar_contents, read, run, run_ok, run_ok_with_stdin, sha1, sha512, start, write};
+use mod_updater::modrinth::Side;
+use mod_updater::{Config, ModManifest};
+use tempfile::tempdir;
+
+const PACK: &str = "loader: fabric\nversion: 1.21.4\nmods:\n- alpha\n";
+
+fn config(dir: &Path) -> Config {
+    serde_yaml::from_str(&read(dir, "mods.yaml")).unwrap()
+}
+
+fn manifest(dir: &Path) -> ModManifest {
+    serde_yaml::from_str(&read(dir, "mods.lock")).unwrap()
+}
+
+/// Lock 'alpha' to its oldest 1.21.4 version
+fn lock_alpha_1_0(dir: &Path, api_url: &str) {
+    let contents = jar_contents("alpha-1.0.jar");
+    let base = api_url.trim_end_matches("/v2");
+    write(
+        dir,
+        "mods.lock",
+        &format!(
+            "installed:
+  alpha:
+    version: alpha-1.0.jar
+    file: alpha-1.0.jar
+    project_id: AAAAAAAA
+    version_id: A0000001
+    url: {base}/files/alpha-1.0.jar
+    size: {}
+    sha512: {}
+    sha1: {}
+",
+            contents.len(),
+            sha512(&contents),
+            sha1(&contents)
+        ),
+    );
+}
+
+#[tokio::test]
+async fn init_creates_config() {
+    let (_server, api_url) = start().await;
+    let dir = tempdir().unwrap();
+
+    run_ok(dir.path(), &api_url, &["pack", "init", "fabric", "1.21.4"]).await;
+
+    let config = config(dir.path());
+    assert_eq!(config.version, "1.21.4");
+    assert!(config.mods.is_empty());
+}
+
+#[tokio::test]
+async fn add_installs_required_dependencies() {
+    let (_server, api_url) = start().await;
+    let dir = tempdir().unwrap();
+    run_ok(dir.path(), &api_url, &["pack", "init", "fabric", "1.21.4"]).await;
+
+    run_ok(dir.path(), &api_url, &["pack", "add", "beta"]).await;
+
+    let config = config(dir.path());
+    assert_eq!(config.mods, ["beta"]);
+    assert_eq!(config.dependencies, ["alpha"]);
+    let manifest = manifest(dir.path());
+    assert_eq!(manifest.installed["beta"].version_id, "B0000001");
+    assert_eq!(manifest.installed["alpha"].version_id, "A0000002");
+    assert!(manifest.installed.values().all(|m| m.is_locked()));
+    assert_eq!(
+        std::fs::read(dir.path().join("alpha-1.1.jar")).unwrap(),
+        jar_contents("alpha-1.1.jar")
+    );
+    assert!(dir.path().join("beta-1.0.jar").exists());
+}
+
+#[tokio::test]
+async fn remove_prunes_dependencies_nothing_requires() {
+    let (_server, api_url) = start().await;
+    let dir = tempdir().unwrap();
+    run_ok(dir.path(), &api_url, &["pack", "init", "fabric", "1.21.4"]).await;
+    run_ok(dir.path(), &api_url, &["pack", "add", "beta"]).await;
+
+    let stdout = run_ok(dir.path(), &api_url, &["pack", "remove", "beta"]).await;
+
+    assert!(stdout.contains("Dependency 'alpha' removed"));
+    let pack = config(dir.path());
+    assert!(pack.mods.is_empty());
+    assert!(pack.dependencies.is_empty());
+    assert!(manifest(dir.path()).installed.is_empty());
+    assert!(!dir.path().join("alpha-1.1.jar").exists());
+
+    // Dependencies can also be removed by name
+    run_ok(dir.path(), &api_url, &["pack", "add", "beta"]).await;
+    run_ok(dir.path(), &api_url, &["pack", "remove", "alpha"]).await;
+    assert!(config(dir.path()).dependencies.is_empty());
+    assert!(!manifest(dir.path()).installed.contains_key("alpha"));
+    assert!(!dir.path().join("alpha-1.1.jar").exists());
+    assert!(dir.path().join("beta-1.0.jar").exists());
+}
+
+#[tokio::test]
+async fn add_rejects_corrupt_download() {
+    let (_server, api_url) = start().await;
+    let dir = tempdir().unwrap();
+    run_ok(dir.path(), &api_url, &["pack", "init", "fabric", "1.21.4"]).await;
+
+    let output = run(dir.path(), &api_url, &["pack", "add", "broken"], "").await;
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Hash mismatch"));
+    assert!(!dir.path().join("broken-1.0.jar").exists());
+    assert!(config(dir.path()).mods.is_empty());
+}
+
+#[tokio::test]
+async fn list_shows_mods_and_dependencies() {
+    let (_server, api_url) = start().await;
+    let dir = tempdir().unwrap();
+    write(
+        dir.path(),
+        "mods.yaml",
+        "loader: fabric\nversion: 1.21.4\nmods:\n- beta\ndependencies:\n- alpha\n",
+    );
+
+    let stdout = run_ok(dir.path(), &api_url, &["pack", "list"]).await;
+
+    assert!(stdout.contains("\tbeta"));
+    assert!(stdout.contains("Dependencies:\n\talpha"));
+}
+
+#[tokio::test]
+async fn remove_deletes_mod_file() {
+    let (_server, api_url) = start().await;
+    let dir = tempdir().unwrap();
+    run_ok(dir.path(), &api_url, &["pack", "init", "fabric", "1.21.4"]).await;
+    run_ok(dir.path(), &api_url, &["pack", "add", "alpha"]).await;
+    assert!(dir.path().join("alpha-1.1.jar").exists());
+
+    run_ok(dir.path(), &api_url, &["pack", "remove", "alpha"]).await;
+
+    assert!(!dir.path().join("alpha-1.1.jar").exists());
+    assert!(config(dir.path()).mods.is_empty());
+    assert!(manifest(dir.path()).installed.is_empty());
+}
+
+#[tokio::test]
+async fn download_installs_missing_mods() {
+    let (_server, api_url) = start().await;
+    let dir = tempdir().unwrap();
+    write(dir.path(), "mods.yaml", PACK);
+
+    run_ok(dir.path(), &api_url, &["pack", "download"]).await;
+
+    assert_eq!(
+        std::fs::read(dir.path().join("alpha-1.1.jar")).unwrap(),
+        jar_contents("alpha-1.1.jar")
+    );
+    assert_eq!(
+        manifest(dir.path()).installed["alpha"].version_id,
+        "A0000002"
+    );
+}
+
+#[tokio::test]
+async fn install_frozen_uses_locked_version() {
+    let (_server, api_url) = start().await;
+    let dir = tempdir().unwrap();
+    write(dir.path(), "mods.yaml", PACK);
+    lock_alpha_1_0(dir.path(), &api_url);
+
+    run_ok(dir.path(), &api_url, &["pack", "install", "--frozen"]).await;
+
+    assert!(dir.path().join("alpha-1.0.jar").exists());
+    assert!(!dir.path().join("alpha-1.1.jar").exists());
+}
+
+#[tokio::test]
+async fn install_frozen_fails_without_lock() {
+    let (_server, api_url) = start().await;
+    let dir = tempdir().unwrap();
+    write(dir.path(), "mods.yaml", PACK);
+
+    let output = run(dir.path(), &api_url, &["pack", "install", "--frozen"], "").await;
+
+    assert!(!output.status.success());
+    assert!(!dir.path().join("alpha-1.1.jar").exists());
+}
+
+#[tokio::test]
+async fn update_replaces_outdated_mod() {
+    let (_server, api_url) = start().await;
+    let dir = tempdir().unwrap();
+    write(dir.path(), "mods.yaml", PACK);
+    lock_alpha_1_0(dir.path(), &api_url);
+    run_ok(dir.path(), &api_url, &["pack", "install", "--frozen"]).await;
+
+    let stdout = run_ok(dir.path(), &api_url, &["pack", "update"]).await;
+
+    assert!(stdout.contains("Updated 'alpha' to 'alpha-1.1.jar'"));
+    assert!(dir.path().join("alpha-1.1.jar").exists());
+    assert!(!dir.path().join("alpha-1.0.jar").exists());
+    assert_eq!(
+        manifest(dir.path()).installed["alpha"].version_id,
+        "A0000002"
+    );
+}
+
+#[tokio::test]
+async fn upgrade_moves_pack_to_selected_game_version() {
+    let (_server, api_url) = start().await;
+    let dir = tempdir().unwrap();
+    write(dir.path(), "mods.yaml", PACK);
+    run_ok(dir.path(), &api_url, &["pack", "download"]).await;
+
+    run_ok_with_stdin(dir.path(), &api_url, &["pack", "upgrade"], "0\n").await;
+
+    assert_eq!(config(dir.path()).version, "1.21.5");
+    assert!(dir.path().join("alpha-2.0.jar").exists());
+    assert!(dir.path().join("old/alpha-1.1.jar").exists());
+    assert!(!dir.path().join("alpha-1.1.jar").exists());
+    assert_eq!(
+        manifest(dir.path()).installed["alpha"].version_id,
+        "A0000003"
+    );
+}
+
+#[tokio::test]
+async fn latest_game_version_lists_newest_supported_version() {
+    let (server, api_url) = start().await;
+    let dir = tempdir().unwrap();
+    write(dir.path(), "mods.yaml", PACK);
+
+    let stdout = run_ok(dir.path(), &api_url, &["pack", "latest-game-version"]).await;
+
+    assert!(stdout.contains("alpha - 1.21.5"));
+    // Versions of all mods are fetched in bulk rather than listed per project
+    let requests = server.received_requests().await.unwrap();
+    assert!(requests.iter().any(|r| r.url.path() == "/v2/versions"));
+    assert!(!requests
+        .iter()
+        .any(|r| r.url.path().starts_with("/v2/project/")));
+}
+
+#[tokio::test]
+async fn export_and_import_round_trip() {
+    let (_server, api_url) = start().await;
+    let dir = tempdir().unwrap();
+    write(
+        dir.path(),
+        "mods.yaml",
+        "loader: fabric\nversion: 1.21.4\nloader_version: 0.16.10\nmods:\n- alpha\n",
+    );
+    run_ok(dir.path(), &api_url, &["pack", "download"]).await;
+    std::fs::create_dir_all(dir.path().join("overrides/config")).unwrap();
+    write(
+        dir.path(),
+        "overrides/config/alpha.toml",
+        "enabled = true\n",
+    );
+
+    run_ok(
+        dir.path(),
+        &api_url,
+        &["pack", "export", "--file", "pack.mrpack"],
+    )
+    .await;
+
+    let imported = tempdir().unwrap();
+    let mrpack = dir.path().join("pack.mrpack");
+    run_ok(
+        imported.path(),
+        &api_url,
+        &["pack", "import", mrpack.to_str().unwrap()],
+    )
+    .await;
+
+    let config = config(imported.path());
+    assert_eq!(config.mods, ["alpha"]);
+    assert_eq!(config.version, "1.21.4");
+    assert_eq!(config.loader_version.as_deref(), Some("0.16.10"));
+    assert_eq!(config.mods_dir.as_deref(), Some(Path::new("mods")));
+    assert!(imported.path().join("mods/alpha-1.1.jar").exists());
+    assert_eq!(
+        read(imported.path(), "config/alpha.toml"),
+        "enabled = true\n"
+    );
+    assert_eq!(
+        manifest(imported.path()).installed["alpha"].version_id,
+        "A0000002"
+    );
+}
+
+#[tokio::test]
+async fn adopt_tracks_jars_found_on_modrinth() {
+    let (_server, api_url) = start().await;
+    let dir = tempdir().unwrap();
+    run_ok(dir.path(), &api_url, &["pack", "init", "fabric", "1.21.4"]).await;
+    std::fs::write(
+        dir.path().join("alpha-1.0.jar"),
+        jar_contents("alpha-1.0.jar"),
+    )
+    .unwrap();
+    std::fs::write(dir.path().join("unknown.jar"), b"not on modrinth").unwrap();
+
+    let stdout = run_ok(dir.path(), &api_url, &["pack", "adopt"]).await;
+
+    assert!(stdout.contains("unknown.jar"));
+    assert_eq!(config(dir.path()).mods, ["alpha"]);
+    let manifest = manifest(dir.path());
+    assert_eq!(manifest.installed["alpha"].version_id, "A0000001");
+    assert_eq!(manifest.installed["alpha"].file, "alpha-1.0.jar");
+}
+
+#[tokio::test]
+async fn api_url_is_read_from_config_and_environment() {
+    let (_server, api_url) = start().await;
+    let dir = tempdir().unwrap();
+    write(
+        dir.path(),
+        "mods.yaml",
+        &format!("{PACK}api_url: {api_url}\n"),
+    );
+
+    let output = common::command(dir.path())
+        .args(["pack", "download"])
+        .output()
+        .await
+        .unwrap();
+    assert!(output.status.success());
+    assert!(dir.path().join("alpha-1.1.jar").exists());
+
+    let output = common::command(dir.path())
+        .env("MODRINTH_API_URL", &api_url)
+        .args(["versions", "alpha"])
+        .output()
+        .await
+        .unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("alpha-2.0.jar - 1.21.5"));
+}
+
+#[tokio::test]
+async fn update_checks_locked_mods_in_one_request() {
+    let (server, api_url) = start().await;
+    let dir = tempdir().unwrap();
+    write(
+        dir.path(),
+        "mods.yaml",
+        "loader: fabric\nversion: 1.21.4\nmods:\n- alpha\n- beta\n",
+    );
+    run_ok(dir.path(), &api_url, &["pack", "download"]).await;
+    let before = server.received_requests().await.unwrap().len();
+
+    let stdout = run_ok(dir.path(), &api_url, &["pack", "update"]).await;
+
+    assert!(stdout.contains("'alpha' is already up to date"));
+    assert!(stdout.contains("'beta' is already up to date"));
+    let requests = server.received_requests().await.unwrap();
+    let paths: Vec<&str> = requests[before..].iter().map(|r| r.url.path()).collect();
+    assert_eq!(paths, ["/v2/version_files/update"]);
+}
+
+#[tokio::test]
+async fn cache_is_skipped_with_no_cache_and_cleared() {
+    let (server, api_url) = start().await;
+    let dir = tempdir().unwrap();
+    write(dir.path(), "mods.yaml", PACK);
+    let game_version_requests = || async {
+        server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .filter(|r| r.url.path() == "/v2/tag/game_version")
+            .count()
+    };
+
+    run_ok(dir.path(), &api_url, &["pack", "latest-game-version"]).await;
+    run_ok(dir.path(), &api_url, &["pack", "latest-game-version"]).await;
+    assert_eq!(game_version_requests().await, 1);
+
+    let args = ["pack", "latest-game-version", "--no-cache"];
+    run_ok(dir.path(), &api_url, &args).await;
+    assert_eq!(game_version_requests().await, 2);
+
+    run_ok(dir.path(), &api_url, &["cache", "clear"]).await;
+    assert!(!dir.path().join(".cache/http").exists());
+}
+
+#[tokio::test]
+async fn offline_uses_cache_and_store() {
+    let (_server, api_url) = start().await;
+    let shared = tempdir().unwrap();
+    let online = tempdir().unwrap();
+    let offline = tempdir().unwrap();
+    let run_shared = |dir: &Path, args: &[&str]| {
+        common::command(dir)
+            .env("MOD_UPDATER_CACHE_DIR", shared.path().join("cache"))
+            .env("MOD_UPDATER_STORE_DIR", shared.path().join("store"))
+            .args(args)
+            .args(["--api-url", &api_url])
+            .output()
+    };
+    write(online.path(), "mods.yaml", PACK);
+    assert!(run_shared(online.path(), &["pack", "download"])
+        .await
+        .unwrap()
+        .status
+        .success());
+
+    write(offline.path(), "mods.yaml", PACK);
+    let output = run_shared(offline.path(), &["pack", "download", "--offline"])
+        .await
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        std::fs::read(offline.path().join("alpha-1.1.jar")).unwrap(),
+        jar_contents("alpha-1.1.jar")
+    );
+
+    // Nothing about 'beta' has been cached
+    let output = run_shared(offline.path(), &["pack", "add", "beta", "--offline"])
+        .await
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("is not available offline"));
+}
+
+#[tokio::test]
+async fn store_is_shared_between_packs_and_collected() {
+    let (server, api_url) = start().await;
+    let store = tempdir().unwrap();
+    let first = tempdir().unwrap();
+    let second = tempdir().unwrap();
+    let run_shared = |dir: &Path, args: &[&str]| {
+        common::command(dir)
+            .env("MOD_UPDATER_STORE_DIR", store.path())
+            .args(args)
+            .args(["--api-url", &api_url])
+            .output()
+    };
+    let blob = |file: &str| {
+        let hash = sha512(&jar_contents(file));
+        store.path().join(&hash[..2]).join(hash)
+    };
+
+    write(first.path(), "mods.yaml", PACK);
+    assert!(run_shared(first.path(), &["pack", "download"])
+        .await
+        .unwrap()
+        .status
+        .success());
+    write(
+        second.path(),
+        "mods.yaml",
+        "loader: fabric\nversion: 1.21.4\nlink_mode: symlink\nmods:\n- alpha\n- beta\n",
+    );
+    assert!(run_shared(second.path(), &["pack", "download"])
+        .await
+        .unwrap()
+        .status
+        .success());
+
+    let jar_downloads = server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .filter(|r| r.url.path() == "/files/alpha-1.1.jar")
+        .count();
+    assert_eq!(jar_downloads, 1);
+    assert!(
+        std::fs::symlink_metadata(second.path().join("alpha-1.1.jar"))
+            .unwrap()
+            .is_symlink()
+    );
+    assert_eq!(
+        std::fs::read(second.path().join("alpha-1.1.jar")).unwrap(),
+        jar_contents("alpha-1.1.jar")
+    );
+
+    assert!(run_shared(second.path(), &["pack", "remove", "beta"])
+        .await
+        .unwrap()
+        .status
+        .success());
+    let output = run_shared(first.path(), &["store", "gc"]).await.unwrap();
+    assert!(output.status.success());
+
+    assert!(blob("alpha-1.1.jar").exists());
+    assert!(!blob("beta-1.0.jar").exists());
+
+    // Files downloaded outside of a pack aren't links that gc could break
+    let loose = tempdir().unwrap();
+    let output = run_shared(
+        loose.path(),
+        &[
+            "download",
+            "beta",
+            "fabric",
+            "1.21.4",
+            "--latest",
+            "--link-mode",
+            "symlink",
+        ],
+    )
+    .await
+    .unwrap();
+    assert!(output.status.success());
+    assert!(blob("beta-1.0.jar").exists());
+    assert!(run_shared(first.path(), &["store", "gc"])
+        .await
+        .unwrap()
+        .status
+        .success());
+    assert!(!blob("beta-1.0.jar").exists());
+    assert_eq!(
+        std::fs::read(loose.path().join("beta-1.0.jar")).unwrap(),
+        jar_contents("beta-1.0.jar")
+    );
+}
+
+#[tokio::test]
+async fn store_rejects_invalid_hashes() {
+    let (_server, api_url) = start().await;
+    let store = tempdir().unwrap();
+    let dir = tempdir().unwrap();
+    write(dir.path(), "mods.yaml", PACK);
+    write(
+        dir.path(),
+        "mods.lock",
+        "installed:
+  alpha:
+    version: alpha-1.0.jar
+    file: alpha-1.0.jar
+    project_id: AAAAAAAA
+    version_id: A0000001
+    url: http://localhost/files/alpha-1.0.jar
+    size: 1
+    sha512: ../../../../etc/passwd
+    sha1: ''
+",
+    );
+
+    let output = common::command(dir.path())
+        .env("MOD_UPDATER_STORE_DIR", store.path())
+        .args(["pack", "install", "--frozen", "--api-url", &api_url])
+        .output()
+        .await
+        .unwrap();
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("is not a valid sha512 hash"));
+    assert!(!dir.path().join("alpha-1.0.jar").exists());
+}
+
+#[tokio::test]
+async fn pack_dir_and_mods_dir_separate_config_and_jars() {
+    let (_server, api_url) = start().await;
+    let dir = tempdir().unwrap();
+    let instance = dir.path().join("instance");
+    let pack_dir = ["--pack-dir", "instance"];
+
+    run_ok(
+        dir.path(),
+        &api_url,
+        &[
+            &["pack", "init", "fabric", "1.21.4", "--mods-dir", "mods"],
+            &pack_dir[..],
+        ]
+        .concat(),
+    )
+    .await;
+    run_ok(
+        dir.path(),
+        &api_url,
+        &[&["pack", "add", "alpha"], &pack_dir[..]].concat(),
+    )
+    .await;
+
+    assert_eq!(
+        config(&instance).mods_dir.as_deref(),
+        Some(Path::new("mods"))
+    );
+    assert!(instance.join("mods.lock").exists());
+    assert!(instance.join("mods/alpha-1.1.jar").exists());
+    assert!(!instance.join("alpha-1.1.jar").exists());
+
+    run_ok(
+        dir.path(),
+        &api_url,
+        &[&["pack", "remove", "alpha"], &pack_dir[..]].concat(),
+    )
+    .await;
+    assert!(!instance.join("mods/alpha-1.1.jar").exists());
+}
+
+#[tokio::test]
+async fn add_shader_installs_into_shaderpacks() {
+    let (_server, api_url) = start().await;
+    let dir = tempdir().unwrap();
+    write(dir.path(), "mods.yaml", PACK);
+
+    run_ok(
+        dir.path(),
+        &api_url,
+        &["pack", "add", "--type", "shader", "glow"],
+    )
+    .await;
+
+    assert!(dir.path().join("shaderpacks/glow-1.0.zip").exists());
+    assert!(read(dir.path(), "mods.yaml").contains("- name: glow\n  type: shader\n"));
+    let stdout = run_ok(dir.path(), &api_url, &["pack", "list"]).await;
+    assert!(stdout.contains("\talpha\n"));
+    assert!(stdout.contains("\tglow (shader)\n"));
+
+    std::fs::remove_file(dir.path().join("shaderpacks/glow-1.0.zip")).unwrap();
+    run_ok(dir.path(), &api_url, &["pack", "install"]).await;
+    assert!(dir.path().join("shaderpacks/glow-1.0.zip").exists());
+    assert!(dir.path().join("alpha-1.1.jar").exists());
+
+    // 'glow' isn't a mod
+    let output = run(dir.path(), &api_url, &["pack", "add", "glow"], "").await;
+    assert!(!output.status.success());
+}
+
+#[tokio::test]
+async fn init_accepts_loaders_as_written_to_config() {
+    let (_server, api_url) = start().await;
+    let dir = tempdir().unwrap();
+
+    run_ok(
+        dir.path(),
+        &api_url,
+        &["pack", "init", "bungeecord", "1.21.4"],
+    )
+    .await;
+
+    assert!(read(dir.path(), "mods.yaml").contains("loader: bungeecord"));
+}
+
+#[tokio::test]
+async fn paper_pack_installs_spigot_plugins_into_plugins() {
+    let (_server, api_url) = start().await;
+    let dir = tempdir().unwrap();
+    run_ok(dir.path(), &api_url, &["pack", "init", "paper", "1.21.4"]).await;
+
+    run_ok(dir.path(), &api_url, &["pack", "add", "sign"]).await;
+
+    assert!(dir.path().join("plugins/sign-1.0.jar").exists());
+    assert_eq!(
+        manifest(dir.path()).installed["sign"].version_id,
+        "E0000001"
+    );
+    let stdout = run_ok(dir.path(), &api_url, &["pack", "update"]).await;
+    assert!(stdout.contains("'sign' is already up to date"));
+
+    // Velocity doesn't run Spigot plugins
+    let proxy = tempdir().unwrap();
+    run_ok(
+        proxy.path(),
+        &api_url,
+        &["pack", "init", "velocity", "1.21.4"],
+    )
+    .await;
+    let output = run(proxy.path(), &api_url, &["pack", "add", "sign"], "").await;
+    assert!(!output.status.success());
+}
+
+#[tokio::test]
+async fn quilt_pack_falls_back_to_fabric_mods() {
+    let (_server, api_url) = start().await;
+    let dir = tempdir().unwrap();
+    run_ok(dir.path(), &api_url, &["pack", "init", "quilt", "1.21.4"]).await;
+
+    run_ok(dir.path(), &api_url, &["pack", "add", "alpha"]).await;
+
+    let installed = &manifest(dir.path()).installed["alpha"];
+    assert_eq!(installed.version_id, "A0000002");
+    assert_eq!(installed.loader, "fabric");
+    let stdout = run_ok(dir.path(), &api_url, &["pack", "update"]).await;
+    assert!(stdout.contains("'alpha' is already up to date"));
+
+    // No fallbacks with strict_loader
+    let strict = tempdir().unwrap();
+    write(
+        strict.path(),
+        "mods.yaml",
+        "loader: quilt\nversion: 1.21.4\nstrict_loader: true\nmods: []\n",
+    );
+    let output = run(strict.path(), &api_url, &["pack", "add", "alpha"], "").await;
+    assert!(!output.status.success());
+}
+
+#[tokio::test]
+async fn download_side_skips_mods_for_the_other_side() {
+    let (_server, api_url) = start().await;
+    let server = tempdir().unwrap();
+    write(server.path(), "mods.yaml", &format!("{PACK}- zoom\n"));
+
+    let stdout = run_ok(
+        server.path(),
+        &api_url,
+        &["pack", "download", "--side", "server"],
+    )
+    .await;
+
+    assert!(stdout.contains("Skipping 'zoom'"));
+    assert!(server.path().join("alpha-1.1.jar").exists());
+    assert!(!server.path().join("zoom-1.0.jar").exists());
+    assert_eq!(
+        manifest(server.path()).installed["alpha"].side,
+        Some(Side::Both)
+    );
+    // The side is remembered by later commands
+    assert_eq!(config(server.path()).side, Some(Side::Server));
+    run_ok(server.path(), &api_url, &["pack", "update"]).await;
+    run_ok(server.path(), &api_url, &["pack", "install"]).await;
+    run_ok(server.path(), &api_url, &["pack", "install", "--frozen"]).await;
+    assert!(!server.path().join("zoom-1.0.jar").exists());
+    assert!(!manifest(server.path()).installed.contains_key("zoom"));
+
+    let client = tempdir().unwrap();
+    write(client.path(), "mods.yaml", &format!("{PACK}- zoom\n"));
+    run_ok(
+        client.path(),
+        &api_url,
+        &["pack", "download", "--side", "client"],
+    )
+    .await;
+    assert!(client.path().join("zoom-1.0.jar").exists());
+    assert_eq!(
+        manifest(client.path()).installed["zoom"].side,
+        Some(Side::Client)
+    );
+
+    // The side in 'mods.yaml' overrides Modrinth's
+    let both = tempdir().unwrap();
+    write(
+        both.path(),
+        "mods.yaml",
+        &format!("{PACK}- name: zoom\n  side: both\n"),
+    );
+    run_ok(
+        both.path(),
+        &api_url,
+        &["pack", "download", "--side", "server"],
+    )
+    .await;
+    assert!(both.path().join("zoom-1.0.jar").exists());
+    assert_eq!(config(both.path()).mods[1].side, Some(Side::Both));
+}
+
+#[tokio::test]
+async fn channel_limits_versions_to_releases_unless_allowed() {
+    let (_server, api_url) = start().await;
+    let dir = tempdir().unwrap();
+    write(
+        dir.path(),
+        "mods.yaml",
+        "loader: fabric\nversion: 1.21.4\nmods:\n- zoom\n",
+    );
+
+    run_ok(dir.path(), &api_url, &["pack", "download"]).await;
+    assert_eq!(
+        manifest(dir.path()).installed["zoom"].version_id,
+        "F0000001"
+    );
+    // The newer beta isn't an update on the release channel
+    let stdout = run_ok(dir.path(), &api_url, &["pack", "update"]).await;
+    assert!(stdout.contains("'zoom' is already up to date"));
+    assert!(dir.path().join("zoom-1.0.jar").exists());
+
+    write(
+        dir.path(),
+        "mods.yaml",
+        "loader: fabric\nversion: 1.21.4\nmods:\n- name: zoom\n  type: mod\n  channel: beta\n",
+    );
+    let stdout = run_ok(dir.path(), &api_url, &["pack", "update"]).await;
+    assert!(stdout.contains("Updated 'zoom' to 'zoom-1.1-beta.jar'"));
+    assert_eq!(
+        manifest(dir.path()).installed["zoom"].version_id,
+        "F0000002"
+    );
+    assert!(dir.path().join("zoom-1.1-beta.jar").exists());
+
+    let stdout = run_ok(
+        dir.path(),
+        &api_url,
+        &["latest", "zoom", "fabric", "1.21.4"],
+    )
+    .await;
+    assert!(stdout.contains("zoom-1.0.jar"));
+    let stdout = run_ok(
+        dir.path(),
+        &api_url,
+        &["latest", "zoom", "fabric", "1.21.4", "--channel", "beta"],
+    )
+    .await;
+    assert!(stdout.contains("zoom-1.1-beta.jar"));
+}
+
+#[tokio::test]
+async fn pinned_mods_are_kept_by_update_and_upgrade() {
+    let (_server, api_url) = start().await;
+    let dir = tempdir().unwrap();
+    write(dir.path(), "mods.yaml", PACK);
+    run_ok(dir.path(), &api_url, &["pack", "download"]).await;
+
+    run_ok(
+        dir.path(),
+        &api_url,
+        &["pack", "pin", "alpha", "alpha-1.0.jar"],
+    )
+    .await;
+
+    assert_eq!(
+        config(dir.path()).mods[0].pin.as_deref(),
+        Some("alpha-1.0.jar")
+    );
+    assert!(dir.path().join("alpha-1.0.jar").exists());
+    assert!(!dir.path().join("alpha-1.1.jar").exists());
+    let stdout = run_ok(dir.path(), &api_url, &["pack", "update"]).await;
+    assert!(stdout.contains("'alpha' is pinned to alpha-1.0.jar"));
+    assert_eq!(
+        manifest(dir.path()).installed["alpha"].version_id,
+        "A0000001"
+    );
+    let stdout = run_ok(dir.path(), &api_url, &["pack", "list"]).await;
+    assert!(stdout.contains("alpha (pinned to alpha-1.0.jar)"));
+
+    // The pinned version only supports 1.21.4
+    let stdout = run_ok(dir.path(), &api_url, &["pack", "upgrade"]).await;
+    assert!(stdout.contains("'alpha' is pinned to alpha-1.0.jar, which blocks upgrading to 1.21.5"));
+    assert_eq!(config(dir.path()).version, "1.21.4");
+
+    run_ok(dir.path(), &api_url, &["pack", "unpin", "alpha"]).await;
+    assert_eq!(config(dir.path()).mods[0].pin, None);
+    let stdout = run_ok(dir.path(), &api_url, &["pack", "update"]).await;
+    assert!(stdout.contains("Updated 'alpha' to 'alpha-1.1.jar'"));
+}
+
+#[tokio::test]
+async fn update_replaces_file_recorded_in_manifest() {
+    let (_server, api_url) = start().await;
+    let dir = tempdir().unwrap();
+    write(dir.path(), "mods.yaml", PACK);
+    // Written by an older version, which only recorded the file
+    write(
+        dir.path(),
+        "mods.lock",
+        "installed:\n  alpha:\n    version: alpha-1.0.jar\n    file: alpha-1.0.jar\n",
+    );
+    write(dir.path(), "alpha-1.0.jar", "alpha-1.0.jar jar contents");
+
+    let stdout = run_ok(dir.path(), &api_url, &["pack", "update"]).await;
+
+    assert!(stdout.contains("Updated 'alpha' to 'alpha-1.1.jar'"));
+    assert!(dir.path().join("alpha-1.1.jar").exists());
+    assert!(!dir.path().join("alpha-1.0.jar").exists());
+    assert!(!dir.path().join("alpha-1.1.jar.part").exists());
+    let installed = &manifest(dir.path()).installed["alpha"];
+    assert_eq!(installed.file, "alpha-1.1.jar");
+    assert!(installed.is_locked());
+
+    run_ok(dir.path(), &api_url, &["pack", "remove", "alpha"]).await;
+    assert!(!dir.path().join("alpha-1.1.jar").exists());
+}
+
+#[tokio::test]
+async fn failed_update_still_records_other_updates() {
+    let (_server, api_url) = start().await;
+    let dir = tempdir().unwrap();
+    write(dir.path(), "mods.yaml", PACK);
+    lock_alpha_1_0(dir.path(), &api_url);
+    run_ok(dir.path(), &api_url, &["pack", "install", "--frozen"]).await;
+    write(
+        dir.path(),
+        "mods.yaml",
+        "loader: fabric\nversion: 1.21.4\nmods:\n- alpha\n- broken\n",
+    );
+
+    let output = run(dir.path(), &api_url, &["pack", "update"], "").await;
+
+    assert!(!output.status.success());
+    assert!(dir.path().join("alpha-1.1.jar").exists());
+    assert!(!dir.path().join("alpha-1.0.jar").exists());
+    assert!(!dir.path().join("broken-1.0.jar").exists());
+    let manifest = manifest(dir.path());
+    assert_eq!(manifest.installed["alpha"].file, "alpha-1.1.jar");
+    assert!(!manifest.installed.contains_key("broken"));
+}
+
+#[tokio::test]
+async fn dry_run_prints_plan_without_changing_pack() {
+    let (_server, api_url) = start().await;
+    let dir = tempdir().unwrap();
+    write(dir.path(), "mods.yaml", PACK);
+    lock_alpha_1_0(dir.path(), &api_url);
+    run_ok(dir.path(), &api_url, &["pack", "install", "--frozen"]).await;
+    let lock = read(dir.path(), "mods.lock");
+
+    let stdout = run_ok(dir.path(), &api_url, &["--dry-run", "pack", "update"]).await;
+    assert!(stdout.contains("Would update 'alpha' from 'alpha-1.0.jar' to 'alpha-1.1.jar'"));
+    assert!(stdout.contains("Would remove 'alpha-1.0.jar'"));
+    let size = jar_contents("alpha-1.1.jar").len();
+    assert!(stdout.contains(&format!("({size} bytes to download)")));
+    assert!(dir.path().join("alpha-1.0.jar").exists());
+    assert!(!dir.path().join("alpha-1.1.jar").exists());
+    assert_eq!(read(dir.path(), "mods.lock"), lock);
+
+    let stdout = run_ok(dir.path(), &api_url, &["pack", "add", "beta", "--dry-run"]).await;
+    assert!(stdout.contains("Would download 'beta-1.0.jar'"));
+    assert!(stdout.contains("Would add 'beta'"));
+    assert!(!dir.path().join("beta-1.0.jar").exists());
+    assert_eq!(read(dir.path(), "mods.yaml"), PACK);
+
+    let stdout = run_ok(
+        dir.path(),
+        &api_url,
+        &["pack", "remove", "alpha", "--dry-run"],
+    )
+    .await;
+    assert!(stdout.contains("Would remove 'alpha' from pack"));
+    assert!(dir.path().join("alpha-1.0.jar").exists());
+
+    let output = run(dir.path(), &api_url, &["pack", "export", "--dry-run"], "").await;
+    assert!(!output.status.success());
+}
+
+#[tokio::test]
+async fn output_json_prints_only_the_result_on_stdout() {
+    let (_server, api_url) = start().await;
+    let dir = tempdir().unwrap();
+    write(dir.path(), "mods.yaml", PACK);
+    lock_alpha_1_0(dir.path(), &api_url);
+    run_ok(dir.path(), &api_url, &["pack", "install", "--frozen"]).await;
+
+    let stdout = run_ok(dir.path(), &api_url, &["pack", "list", "--output", "json"]).await;
+    let list: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(list["mods"][0]["name"], "alpha");
+    assert_eq!(list["mods"][0]["installed"]["version_id"], "A0000001");
+
+    let stdout = run_ok(
+        dir.path(),
+        &api_url,
+        &["pack", "update", "--output", "json"],
+    )
+    .await;
+    let updates: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(updates[0]["name"], "alpha");
+    assert_eq!(updates[0]["status"], "updated");
+    assert_eq!(updates[0]["from"]["version_id"], "A0000001");
+    assert_eq!(updates[0]["to"]["name"], "alpha-1.1.jar");
+
+    let stdout = run_ok(dir.path(), &api_url, &["pack", "list", "--output", "yaml"]).await;
+    let list: serde_yaml::Value = serde_yaml::from_str(&stdout).unwrap();
+    assert_eq!(
+        list["mods"][0]["installed"]["file"].as_str(),
+        Some("alpha-1.1.jar")
+    );
+}
+
+#[tokio::test]
+async fn output_json_reports_pack_changes() {
+    let (_server, api_url) = start().await;
+    let dir = tempdir().unwrap();
+    run_ok(
+        dir.path(),
+        &api_url,
+        &["--output", "json", "pack", "init", "fabric", "1.21.4"],
+    )
+    .await;
+
+    let stdout = run_ok(
+        dir.path(),
+        &api_url,
+        &["pack", "add", "beta", "--output", "json"],
+    )
+    .await;
+    let changes: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(changes["added"], serde_json::json!(["beta", "alpha"]));
+    assert_eq!(changes["installed"]["alpha"]["version_id"], "A0000002");
+    assert_eq!(changes["installed"]["beta"]["version_id"], "B0000001");
+
+    let stdout = run_ok(
+        dir.path(),
+        &api_url,
+        &["pack", "pin", "beta", "--output", "json"],
+    )
+    .await;
+    let pin: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(pin["name"], "beta");
+    assert!(pin["pin"].is_string());
+
+    // Commands without a result fail instead of printing nothing
+    let output = run(
+        dir.path(),
+        &api_url,
+        &["pack", "pin", "nope", "--output", "json"],
+        "",
+    )
+    .await;
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No mod 'nope' in pack"));
+
+    let stdout = run_ok(
+        dir.path(),
+        &api_url,
+        &["pack", "remove", "beta", "--output", "json"],
+    )
+    .await;
+    let changes: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(changes["removed"], serde_json::json!(["beta", "alpha"]));
+}
+
+#[tokio::test]
+async fn yes_chooses_without_prompting_or_lists_candidates() {
+    let (_server, api_url) = start().await;
+    let dir = tempdir().unwrap();
+    write(dir.path(), "mods.yaml", PACK);
+
+    run_ok(
+        dir.path(),
+        &api_url,
+        &["download", "alpha", "fabric", "1.21.4", "--yes"],
+    )
+    .await;
+    assert!(dir.path().join("alpha-1.1.jar").exists());
+
+    let output = run(dir.path(), &api_url, &["pack", "add", "a", "--yes"], "").await;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("candidates are: alpha, beta"));
+
+    run_ok(dir.path(), &api_url, &["pack", "download"]).await;
+    let output = run(
+        dir.path(),
+        &api_url,
+        &["pack", "upgrade", "--non-interactive", "--to", "1.21.3"],
+        "",
+    )
+    .await;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Can't upgrade to '1.21.3', compatible game versions are: 1.21.5"));
+
+    run_ok(
+        dir.path(),
+        &api_url,
+        &["pack", "upgrade", "--non-interactive", "--to", "1.21.5"],
+    )
+    .await;
+    assert_eq!(config(dir.path()).version, "1.21.5");
+}
+
+#[tokio::test]
+async fn outdated_lists_newer_versions_and_fails() {
+    let (server, api_url) = start().await;
+    let dir = tempdir().unwrap();
+    write(dir.path(), "mods.yaml", PACK);
+    lock_alpha_1_0(dir.path(), &api_url);
+
+    let output = run(dir.path(), &api_url, &["pack", "outdated"], "").await;
+    assert_eq!(output.status.code(), Some(2));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Mod    Current        Published   Latest         Published"));
+    assert!(stdout.contains("alpha  alpha-1.0.jar  2024-12-10  alpha-1.1.jar  2025-01-01"));
+    // Locked mods are checked in bulk by the hash of their file
+    let requests = server.received_requests().await.unwrap();
+    assert!(!requests
+        .iter()
+        .any(|r| r.url.path().starts_with("/v2/project/")
+            || r.url.path().starts_with("/v2/version/")));
+
+    let output = run(
+        dir.path(),
+        &api_url,
+        &["pack", "outdated", "--output", "json"],
+        "",
+    )
+    .await;
+    assert_eq!(output.status.code(), Some(2));
+    let outdated: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(outdated[0]["current"], "alpha-1.0.jar");
+    assert_eq!(outdated[0]["current_published"], "2024-12-10");
+    assert_eq!(outdated[0]["latest_published"], "2025-01-01");
+
+    run_ok(dir.path(), &api_url, &["pack", "update"]).await;
+    let stdout = run_ok(dir.path(), &api_url, &["pack", "outdated"]).await;
+    assert!(stdout.contains("All mods are up to date"));
+}